@@ -0,0 +1,374 @@
+//! Checks evaluated against a response body and reported in
+//! `assertion_results`.
+//!
+//! An assertion compares two operands. Each operand is a constant, the value
+//! at a JSONPath, or a `sum`/`count` over the values a path matches, so one
+//! shape covers `status == "ok"`, `created_at <= updated_at` and
+//! `total == sum(items[*].amount)`. Assertions see the body as received,
+//! before `select` and `transforms`.
+//!
+//! Checks on response metadata, such as `assert_content_type`,
+//! `max_age_seconds`, `require_cacheable` and `cookie_assertions`, are
+//! reported in fields of their own and folded into `assertions_passed`
+//! alongside these.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::jsonpath;
+
+/// The comparison names accepted in `op`.
+pub const OPS: [&str; 6] = ["eq", "ne", "lt", "le", "gt", "ge"];
+/// The operand kinds accepted on either side of an assertion.
+pub const OPERANDS: [&str; 4] = ["path", "sum", "count", "value"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Assertion {
+    pub left: Operand,
+    pub op: CompareOp,
+    pub right: Operand,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Operand {
+    /// The value at a path; a wildcard path yields the array of matches.
+    Path(String),
+    /// The sum of the numbers a path matches. A path to a single array sums
+    /// its items.
+    Sum(String),
+    /// How many values a path matches. A path to a single array counts its
+    /// items.
+    Count(String),
+    Value(Value),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AssertionResult {
+    pub assertion: Assertion,
+    pub passed: bool,
+    /// The operands as resolved against the body.
+    pub left: Option<Value>,
+    pub right: Option<Value>,
+    /// Why the assertion could not be evaluated, e.g. a path matched nothing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Assertion {
+    pub fn validate(&self) -> Result<(), String> {
+        for operand in [&self.left, &self.right] {
+            if let Operand::Path(path) | Operand::Sum(path) | Operand::Count(path) = operand {
+                jsonpath::parse(path).map_err(|e| format!("Invalid assertion path: {}", e))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn evaluate(&self, body: &Value) -> AssertionResult {
+        let left = self.left.resolve(body);
+        let right = self.right.resolve(body);
+        let outcome = match (&left, &right) {
+            (Ok(l), Ok(r)) => compare(l, self.op, r),
+            (Err(e), _) | (_, Err(e)) => Err(e.clone()),
+        };
+        AssertionResult {
+            assertion: self.clone(),
+            passed: outcome.as_ref().is_ok_and(|passed| *passed),
+            left: left.ok(),
+            right: right.ok(),
+            error: outcome.err(),
+        }
+    }
+}
+
+impl Operand {
+    fn resolve(&self, body: &Value) -> Result<Value, String> {
+        match self {
+            Operand::Value(value) => Ok(value.clone()),
+            Operand::Path(path) => {
+                jsonpath::query(body, path)?.ok_or_else(|| format!("Path '{}' matched nothing", path))
+            }
+            Operand::Count(path) => Ok(Value::from(items(body, path)?.len())),
+            Operand::Sum(path) => {
+                let mut sum = 0.0;
+                for item in items(body, path)? {
+                    sum += item
+                        .as_f64()
+                        .ok_or_else(|| format!("Cannot sum non-numeric value {} at '{}'", item, path))?;
+                }
+                Ok(Value::from(sum))
+            }
+        }
+    }
+}
+
+/// The values an aggregate runs over: every match, or the items of the one
+/// array a non-wildcard path points at.
+fn items<'a>(body: &'a Value, path: &str) -> Result<Vec<&'a Value>, String> {
+    let segments = jsonpath::parse(path)?;
+    let matches = jsonpath::select_all(body, &segments);
+    match matches.as_slice() {
+        [Value::Array(array)] if !segments.contains(&jsonpath::Segment::Wildcard) => Ok(array.iter().collect()),
+        _ => Ok(matches),
+    }
+}
+
+/// Numbers compare by value, so `3` equals `3.0`; strings compare
+/// lexically, which orders ISO 8601 timestamps correctly. Ordering any other
+/// pair of values is an error.
+fn compare(left: &Value, op: CompareOp, right: &Value) -> Result<bool, String> {
+    let ordering = match (left, right) {
+        (Value::Number(l), Value::Number(r)) => l.as_f64().zip(r.as_f64()).and_then(|(l, r)| l.partial_cmp(&r)),
+        (Value::String(l), Value::String(r)) => Some(l.cmp(r)),
+        _ => None,
+    };
+    match (op, ordering) {
+        (CompareOp::Eq, Some(ordering)) => Ok(ordering.is_eq()),
+        (CompareOp::Ne, Some(ordering)) => Ok(ordering.is_ne()),
+        (CompareOp::Eq, None) => Ok(left == right),
+        (CompareOp::Ne, None) => Ok(left != right),
+        (CompareOp::Lt, Some(ordering)) => Ok(ordering.is_lt()),
+        (CompareOp::Le, Some(ordering)) => Ok(ordering.is_le()),
+        (CompareOp::Gt, Some(ordering)) => Ok(ordering.is_gt()),
+        (CompareOp::Ge, Some(ordering)) => Ok(ordering.is_ge()),
+        (_, None) => Err(format!("Cannot order {} and {}", left, right)),
+    }
+}
+
+/// Checks a `Content-Type` against a media type pattern in which `*` matches
+/// any run of characters, e.g. `application/*json`. Parameters such as
+/// `charset` are ignored and the comparison is case-insensitive. A missing
+/// header never matches.
+pub fn content_type_matches(pattern: &str, content_type: Option<&str>) -> bool {
+    let Some(content_type) = content_type else {
+        return false;
+    };
+    let media_type = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    wildcard_matches(&pattern.trim().to_ascii_lowercase(), &media_type)
+}
+
+fn wildcard_matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// How old a response is, in seconds: the `Age` header when a cache set one,
+/// otherwise the time since its `Date`. A `Date` in the future counts as
+/// age 0. `None` when neither header can be read.
+pub fn response_age(age: Option<&str>, date: Option<&str>, now: DateTime<Utc>) -> Option<u64> {
+    if let Some(age) = age.and_then(|age| age.trim().parse::<u64>().ok()) {
+        return Some(age);
+    }
+    let date = DateTime::parse_from_rfc2822(date?.trim()).ok()?;
+    Some((now - date.with_timezone(&Utc)).num_seconds().max(0) as u64)
+}
+
+/// Whether a response advertises that it may be cached, and why.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Cacheability {
+    pub cacheable: bool,
+    pub reason: String,
+}
+
+/// Judges cacheability from the caching headers: `no-store` rules it out,
+/// a positive `max-age` or `s-maxage` makes it fresh for that long, and an
+/// `ETag` or `Last-Modified` lets a cache revalidate it.
+pub fn cacheability(cache_control: Option<&str>, etag: Option<&str>, last_modified: Option<&str>) -> Cacheability {
+    let directives: Vec<(String, Option<&str>)> = cache_control
+        .unwrap_or("")
+        .split(',')
+        .filter_map(|directive| {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name, Some(value.trim().trim_matches('"'))),
+                None => (directive, None),
+            };
+            let name = name.trim().to_ascii_lowercase();
+            (!name.is_empty()).then_some((name, value))
+        })
+        .collect();
+    let verdict = |cacheable: bool, reason: String| Cacheability { cacheable, reason };
+
+    if directives.iter().any(|(name, _)| name == "no-store") {
+        return verdict(false, "Cache-Control forbids storing it (no-store)".to_string());
+    }
+    let lifetime = directives
+        .iter()
+        .filter(|(name, _)| name == "max-age" || name == "s-maxage")
+        .find_map(|(name, value)| value.and_then(|v| v.parse::<u64>().ok()).map(|seconds| (name, seconds)))
+        .filter(|(_, seconds)| *seconds > 0);
+    if let Some((name, seconds)) = lifetime {
+        return verdict(true, format!("Cache-Control {}={}", name, seconds));
+    }
+    match (etag, last_modified) {
+        (Some(_), _) => verdict(true, "ETag allows revalidation".to_string()),
+        (None, Some(_)) => verdict(true, "Last-Modified allows revalidation".to_string()),
+        (None, None) => verdict(false, "No Cache-Control max-age, ETag or Last-Modified".to_string()),
+    }
+}
+
+/// A check on a cookie the response sets.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CookieAssertion {
+    pub name: String,
+    /// Whether the cookie must be set (the default) or must not be.
+    pub exists: Option<bool>,
+    /// A regex the cookie's value must match.
+    pub value_matches: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CookieAssertionResult {
+    pub assertion: CookieAssertion,
+    pub passed: bool,
+    /// The value the response set, if it set the cookie.
+    pub value: Option<String>,
+}
+
+impl CookieAssertion {
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.value_matches {
+            Some(pattern) => regex::Regex::new(pattern)
+                .map(|_| ())
+                .map_err(|e| format!("Invalid value_matches for cookie '{}': {}", self.name, e)),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks the cookie against the response's `Set-Cookie` headers. When
+    /// the name is set more than once the last one counts, and a cookie set
+    /// with `Max-Age=0` or a past `Expires` is being deleted, so it does not
+    /// exist.
+    pub fn evaluate(&self, set_cookies: &[String], now: DateTime<Utc>) -> CookieAssertionResult {
+        let value = set_cookies
+            .iter()
+            .rev()
+            .find_map(|header| {
+                let mut attributes = header.split(';');
+                let (name, value) = attributes.next()?.split_once('=')?;
+                (name.trim() == self.name).then(|| (value.trim().to_string(), attributes))
+            })
+            .and_then(|(value, attributes)| (!is_deletion(attributes, now)).then_some(value));
+        let passed = match (self.exists.unwrap_or(true), &value) {
+            (false, value) => value.is_none(),
+            (true, None) => false,
+            (true, Some(value)) => self
+                .value_matches
+                .as_deref()
+                .and_then(|pattern| regex::Regex::new(pattern).ok())
+                .is_none_or(|pattern| pattern.is_match(value)),
+        };
+        CookieAssertionResult { assertion: self.clone(), passed, value }
+    }
+}
+
+/// Whether a cookie's attributes expire it immediately. `Max-Age` takes
+/// precedence over `Expires`.
+fn is_deletion<'a>(attributes: impl Iterator<Item = &'a str>, now: DateTime<Utc>) -> bool {
+    let mut expires = None;
+    for attribute in attributes {
+        let (name, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("max-age") {
+            return value.trim().parse::<i64>().is_ok_and(|seconds| seconds <= 0);
+        }
+        if name.eq_ignore_ascii_case("expires") {
+            expires = DateTime::parse_from_rfc2822(value.trim()).ok();
+        }
+    }
+    expires.is_some_and(|at| at.with_timezone(&Utc) <= now)
+}
+
+/// Evaluates every assertion, in order.
+pub fn evaluate_all(assertions: &[Assertion], body: &Value) -> Vec<AssertionResult> {
+    assertions.iter().map(|assertion| assertion.evaluate(body)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn check(assertion: Value, body: &Value) -> AssertionResult {
+        serde_json::from_value::<Assertion>(assertion).unwrap().evaluate(body)
+    }
+
+    #[test]
+    fn sum_and_count_cover_wildcards_and_plain_arrays() {
+        let body = json!({
+            "total": 6,
+            "count": 3,
+            "items": [{"amount": 1}, {"amount": 2.0}, {"amount": 3}],
+        });
+        let sum = check(json!({"left": {"path": "total"}, "op": "eq", "right": {"sum": "items[*].amount"}}), &body);
+        assert!(sum.passed, "{:?}", sum);
+        assert_eq!(sum.right, Some(json!(6.0)));
+
+        let count = check(json!({"left": {"count": "items"}, "op": "eq", "right": {"path": "count"}}), &body);
+        assert!(count.passed, "{:?}", count);
+        let wildcard = check(json!({"left": {"count": "items[*]"}, "op": "ge", "right": {"value": 3}}), &body);
+        assert!(wildcard.passed, "{:?}", wildcard);
+    }
+
+    #[test]
+    fn strings_order_lexically_so_timestamps_compare() {
+        let body = json!({"created_at": "2024-01-02T00:00:00Z", "updated_at": "2024-01-10T00:00:00Z"});
+        let ordered = json!({"left": {"path": "created_at"}, "op": "le", "right": {"path": "updated_at"}});
+        assert!(check(ordered, &body).passed);
+        let reversed = json!({"left": {"path": "created_at"}, "op": "gt", "right": {"path": "updated_at"}});
+        let result = check(reversed, &body);
+        assert!(!result.passed);
+        assert!(result.error.is_none());
+    }
+
+    #[test]
+    fn failures_to_evaluate_are_reported_as_errors() {
+        let body = json!({"status": "ok", "tags": ["a", 1]});
+        let missing = check(json!({"left": {"path": "missing"}, "op": "eq", "right": {"value": 1}}), &body);
+        assert!(!missing.passed);
+        assert!(missing.error.unwrap().contains("matched nothing"));
+
+        let mixed = check(json!({"left": {"path": "status"}, "op": "lt", "right": {"value": 1}}), &body);
+        assert!(mixed.error.unwrap().contains("Cannot order"));
+
+        let non_numeric = check(json!({"left": {"sum": "tags"}, "op": "eq", "right": {"value": 1}}), &body);
+        assert!(non_numeric.error.unwrap().contains("Cannot sum"));
+
+        // Equality still works across types; it is simply false.
+        let unequal = check(json!({"left": {"path": "status"}, "op": "ne", "right": {"value": 1}}), &body);
+        assert!(unequal.passed);
+    }
+
+    #[test]
+    fn validate_rejects_malformed_paths() {
+        let assertion: Assertion =
+            serde_json::from_value(json!({"left": {"sum": "items[x"}, "op": "eq", "right": {"value": 0}})).unwrap();
+        assert!(assertion.validate().is_err());
+    }
+}
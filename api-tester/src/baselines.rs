@@ -0,0 +1,113 @@
+//! Named latency baselines for catching performance regressions.
+//!
+//! A baseline is the mean and standard deviation of a set of timed runs of a
+//! request. Baselines live in memory; when `BASELINE_DIR` is set each one is
+//! also written there as `<name>.json` and all of them are loaded back at
+//! startup, so a CI job can record once and compare on later runs.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+
+/// Tolerance used when a check gives neither `stddevs` nor `percent`.
+pub const DEFAULT_TOLERANCE_STDDEVS: f64 = 3.0;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LatencyBaseline {
+    pub samples: usize,
+    pub mean_ms: f64,
+    pub stddev_ms: f64,
+    pub recorded_at: String,
+}
+
+impl LatencyBaseline {
+    pub fn from_samples(samples_ms: &[f64]) -> Self {
+        let count = samples_ms.len().max(1) as f64;
+        let mean_ms = samples_ms.iter().sum::<f64>() / count;
+        let variance = samples_ms.iter().map(|s| (s - mean_ms).powi(2)).sum::<f64>() / count;
+        Self {
+            samples: samples_ms.len(),
+            mean_ms,
+            stddev_ms: variance.sqrt(),
+            recorded_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// The slowest latency still within tolerance. When both tolerances are
+    /// given the more lenient one applies.
+    pub fn threshold_ms(&self, stddevs: Option<f64>, percent: Option<f64>) -> f64 {
+        let by_stddev = stddevs.map(|k| self.mean_ms + k * self.stddev_ms);
+        let by_percent = percent.map(|p| self.mean_ms * (1.0 + p / 100.0));
+        match (by_stddev, by_percent) {
+            (Some(a), Some(b)) => a.max(b),
+            (Some(a), None) | (None, Some(a)) => a,
+            (None, None) => self.mean_ms + DEFAULT_TOLERANCE_STDDEVS * self.stddev_ms,
+        }
+    }
+}
+
+pub struct BaselineStore {
+    dir: Option<PathBuf>,
+    latency: RwLock<HashMap<String, LatencyBaseline>>,
+}
+
+impl BaselineStore {
+    /// Creates the store, loading any baselines persisted in `BASELINE_DIR`.
+    pub fn load() -> std::io::Result<Self> {
+        let dir = std::env::var("BASELINE_DIR").ok().filter(|d| !d.is_empty()).map(PathBuf::from);
+        let mut latency = HashMap::new();
+        if let Some(dir) = &dir {
+            std::fs::create_dir_all(dir)?;
+            for entry in std::fs::read_dir(dir)? {
+                let path = entry?.path();
+                let Some(name) = path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .and_then(|n| n.strip_suffix(".json"))
+                else {
+                    continue;
+                };
+                match std::fs::read(&path).map_err(|e| e.to_string()).and_then(|bytes| {
+                    serde_json::from_slice::<LatencyBaseline>(&bytes).map_err(|e| e.to_string())
+                }) {
+                    Ok(baseline) => {
+                        latency.insert(name.to_string(), baseline);
+                    }
+                    Err(e) => log::warn!("Skipping baseline {}: {}", path.display(), e),
+                }
+            }
+            log::info!("Loaded {} latency baselines from {}", latency.len(), dir.display());
+        }
+        Ok(Self { dir, latency: RwLock::new(latency) })
+    }
+
+    pub fn get_latency(&self, name: &str) -> Option<LatencyBaseline> {
+        self.latency.read().unwrap().get(name).cloned()
+    }
+
+    /// Stores a baseline, replacing any previous one with the same name.
+    pub fn set_latency(&self, name: &str, baseline: LatencyBaseline) -> Result<(), String> {
+        validate_name(name)?;
+        if let Some(dir) = &self.dir {
+            let json = serde_json::to_vec_pretty(&baseline).map_err(|e| e.to_string())?;
+            std::fs::write(dir.join(format!("{}.json", name)), json)
+                .map_err(|e| format!("Failed to persist baseline: {}", e))?;
+        }
+        self.latency.write().unwrap().insert(name.to_string(), baseline);
+        Ok(())
+    }
+}
+
+/// Names become file names, so they are limited to a safe character set.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        && !name.starts_with('.');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid baseline name '{}': use letters, digits, '-', '_' and '.'", name))
+    }
+}
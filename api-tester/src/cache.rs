@@ -0,0 +1,214 @@
+//! Response cache shared by the proxy handlers.
+//!
+//! Entries always live in an in-process moka cache. When the crate is built
+//! with the `redis` feature and `REDIS_URL` is set, Redis sits behind it as a
+//! shared, durable second tier: lookups that miss locally fall through to
+//! Redis, and inserts are written to both. Redis failures are logged and
+//! treated as misses so a flaky cache server never fails a request.
+
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use moka::future::Cache;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[cfg(feature = "redis")]
+const REDIS_KEY_PREFIX: &str = "api-tester:cache:";
+
+/// The local tier and the TTL it was built with. Swapped as a unit when the
+/// cache is reconfigured.
+struct Local<V: Clone + Send + Sync + 'static> {
+    cache: Cache<String, V>,
+    #[cfg_attr(not(feature = "redis"), allow(dead_code))]
+    ttl: Duration,
+}
+
+/// A value the cache can weigh. Its JSON form covers most of it; fields kept
+/// out of that form, such as `#[serde(skip)]` byte buffers, are added through
+/// `unserialized_bytes` so they still count against the limit.
+pub trait Weigh: Serialize {
+    fn unserialized_bytes(&self) -> usize {
+        0
+    }
+}
+
+#[derive(Clone)]
+pub struct ResponseCache<V: Clone + Send + Sync + 'static> {
+    local: Arc<RwLock<Local<V>>>,
+    #[cfg(feature = "redis")]
+    redis: Option<redis::aio::ConnectionManager>,
+}
+
+impl<V> ResponseCache<V>
+where
+    V: Weigh + DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    /// Creates a cache whose local tier evicts once the entries it holds add
+    /// up to `max_weight_bytes`, weighing each entry by its serialized size.
+    pub async fn new(max_weight_bytes: u64, ttl: Duration) -> Self {
+        let local = Arc::new(RwLock::new(build_local(max_weight_bytes, ttl)));
+        let redis_url = std::env::var("REDIS_URL").ok().filter(|url| !url.is_empty());
+
+        #[cfg(feature = "redis")]
+        {
+            let redis = match redis_url {
+                Some(url) => connect(&url).await,
+                None => None,
+            };
+            Self { local, redis }
+        }
+        #[cfg(not(feature = "redis"))]
+        {
+            if redis_url.is_some() {
+                log::warn!("REDIS_URL is set but this build lacks the `redis` feature; using the in-memory cache only");
+            }
+            Self { local }
+        }
+    }
+
+    /// Replaces the local tier with one built from the new limits. Entries
+    /// cached so far are dropped; Redis entries keep the TTL they were written
+    /// with.
+    pub fn reconfigure(&self, max_weight_bytes: u64, ttl: Duration) {
+        *self.local.write().unwrap() = build_local(max_weight_bytes, ttl);
+    }
+
+    /// The current local tier. moka caches are cheap handles, so cloning one
+    /// out keeps the lock from being held across an await.
+    fn local(&self) -> Cache<String, V> {
+        self.local.read().unwrap().cache.clone()
+    }
+
+    pub async fn get(&self, key: &str) -> Option<V> {
+        let local = self.local();
+        if let Some(value) = local.get(key).await {
+            return Some(value);
+        }
+        #[cfg(feature = "redis")]
+        if let Some(value) = self.redis_get(key).await {
+            local.insert(key.to_string(), value.clone()).await;
+            return Some(value);
+        }
+        None
+    }
+
+    pub async fn insert(&self, key: String, value: V) {
+        #[cfg(feature = "redis")]
+        self.redis_set(&key, &value).await;
+        self.local().insert(key, value).await;
+    }
+
+    #[cfg(feature = "redis")]
+    async fn redis_get(&self, key: &str) -> Option<V> {
+        let mut conn = self.redis.clone()?;
+        let raw: Option<String> = match redis::AsyncCommands::get(&mut conn, redis_key(key)).await {
+            Ok(raw) => raw,
+            Err(e) => {
+                log::warn!("Redis cache lookup failed: {}", e);
+                return None;
+            }
+        };
+        raw.and_then(|raw| match serde_json::from_str(&raw) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                log::warn!("Discarding unreadable Redis cache entry: {}", e);
+                None
+            }
+        })
+    }
+
+    #[cfg(feature = "redis")]
+    async fn redis_set(&self, key: &str, value: &V) {
+        let Some(mut conn) = self.redis.clone() else {
+            return;
+        };
+        let raw = match serde_json::to_string(value) {
+            Ok(raw) => raw,
+            Err(e) => {
+                log::warn!("Could not serialize cache entry for Redis: {}", e);
+                return;
+            }
+        };
+        let ttl = self.local.read().unwrap().ttl;
+        let result: redis::RedisResult<()> =
+            redis::AsyncCommands::set_ex(&mut conn, redis_key(key), raw, ttl.as_secs()).await;
+        if let Err(e) = result {
+            log::warn!("Redis cache write failed: {}", e);
+        }
+    }
+}
+
+fn build_local<V>(max_weight_bytes: u64, ttl: Duration) -> Local<V>
+where
+    V: Weigh + Clone + Send + Sync + 'static,
+{
+    let cache = Cache::builder()
+        .weigher(|key: &String, value: &V| weigh(key, value))
+        .max_capacity(max_weight_bytes)
+        .time_to_live(ttl)
+        .build();
+    Local { cache, ttl }
+}
+
+/// Approximate memory cost of an entry: its key plus the JSON form of the
+/// value and any bytes that form leaves out. Entries too large to weigh are
+/// given the maximum weight so they are evicted first rather than slipping in
+/// for free.
+fn weigh<V: Weigh>(key: &str, value: &V) -> u32 {
+    serde_json::to_vec(value)
+        .ok()
+        .and_then(|bytes| u32::try_from(bytes.len() + key.len() + value.unserialized_bytes()).ok())
+        .unwrap_or(u32::MAX)
+}
+
+#[cfg(feature = "redis")]
+fn redis_key(key: &str) -> String {
+    format!("{}{}", REDIS_KEY_PREFIX, key)
+}
+
+#[cfg(feature = "redis")]
+async fn connect(url: &str) -> Option<redis::aio::ConnectionManager> {
+    let client = match redis::Client::open(url) {
+        Ok(client) => client,
+        Err(e) => {
+            log::error!("Invalid REDIS_URL: {}", e);
+            return None;
+        }
+    };
+    match redis::aio::ConnectionManager::new(client).await {
+        Ok(conn) => {
+            log::info!("Using Redis response cache");
+            Some(conn)
+        }
+        Err(e) => {
+            log::error!("Could not connect to Redis, using the in-memory cache only: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Entry {
+        status: u16,
+        #[serde(skip)]
+        raw: Vec<u8>,
+    }
+
+    impl Weigh for Entry {
+        fn unserialized_bytes(&self) -> usize {
+            self.raw.len()
+        }
+    }
+
+    #[test]
+    fn skipped_bytes_count_towards_the_weight() {
+        let light = weigh("key", &Entry { status: 200, raw: Vec::new() });
+        let heavy = weigh("key", &Entry { status: 200, raw: vec![0; 4096] });
+        assert_eq!(heavy - light, 4096);
+    }
+}
@@ -0,0 +1,121 @@
+//! Failure injection on the `/proxy` path, for checking how clients and
+//! monitoring cope with a misbehaving upstream.
+//!
+//! Injection is configured at runtime via `POST /chaos/config`, but only
+//! when the server was started with `CHAOS_ENABLED=true`; otherwise the
+//! endpoint refuses and nothing is ever injected. Affected responses carry an
+//! `X-Chaos-Injected` header naming the mode.
+
+use std::sync::RwLock;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Upper bound for `latency_ms`, matching the cap on injected request delays.
+pub const MAX_LATENCY_MS: u64 = 60_000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChaosMode {
+    /// Returns a synthetic 500 without contacting the upstream.
+    Error,
+    /// Delays the reply by `latency_ms`.
+    Latency,
+    /// Cuts the reply body in half.
+    Truncate,
+}
+
+impl ChaosMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChaosMode::Error => "error",
+            ChaosMode::Latency => "latency",
+            ChaosMode::Truncate => "truncate",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChaosConfig {
+    /// Chance, from 0 to 1, that a request is affected. 0 turns injection off.
+    pub probability: f64,
+    /// Modes to choose from, uniformly, for an affected request.
+    #[serde(default = "default_modes")]
+    pub modes: Vec<ChaosMode>,
+    #[serde(default = "default_latency_ms")]
+    pub latency_ms: u64,
+}
+
+fn default_modes() -> Vec<ChaosMode> {
+    vec![ChaosMode::Error, ChaosMode::Latency, ChaosMode::Truncate]
+}
+
+fn default_latency_ms() -> u64 {
+    1000
+}
+
+impl ChaosConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.probability) {
+            return Err("probability must be between 0 and 1".to_string());
+        }
+        if self.modes.is_empty() {
+            return Err("modes must not be empty".to_string());
+        }
+        if self.latency_ms > MAX_LATENCY_MS {
+            return Err(format!("latency_ms must be at most {}", MAX_LATENCY_MS));
+        }
+        Ok(())
+    }
+}
+
+pub struct Chaos {
+    enabled: bool,
+    config: RwLock<Option<ChaosConfig>>,
+}
+
+impl Chaos {
+    /// Reads `CHAOS_ENABLED` once; it cannot be switched on after startup.
+    pub fn from_env() -> Self {
+        let enabled = std::env::var("CHAOS_ENABLED")
+            .is_ok_and(|value| matches!(value.trim().to_ascii_lowercase().as_str(), "true" | "1"));
+        if enabled {
+            log::warn!("Chaos injection is enabled; configure it via POST /chaos/config");
+        }
+        Self { enabled, config: RwLock::new(None) }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn config(&self) -> Option<ChaosConfig> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Replaces the configuration. Fails when injection is disabled.
+    pub fn configure(&self, config: ChaosConfig) -> Result<(), String> {
+        if !self.enabled {
+            return Err("Chaos injection is disabled; start the server with CHAOS_ENABLED=true".to_string());
+        }
+        config.validate()?;
+        *self.config.write().unwrap() = Some(config);
+        Ok(())
+    }
+
+    /// Rolls for the current request, returning the mode to inject and the
+    /// latency to use if it is `latency`.
+    pub fn roll(&self) -> Option<(ChaosMode, u64)> {
+        if !self.enabled {
+            return None;
+        }
+        let config = self.config.read().unwrap();
+        let config = config.as_ref()?;
+        let mut rng = rand::thread_rng();
+        if !rng.gen_bool(config.probability) {
+            return None;
+        }
+        config.modes.choose(&mut rng).map(|mode| (*mode, config.latency_ms))
+    }
+}
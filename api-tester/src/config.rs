@@ -0,0 +1,252 @@
+//! Settings that can be tuned while the server is running.
+//!
+//! Values come from the process environment, overlaid with the `KEY=VALUE`
+//! lines of the file named by `CONFIG_FILE` if it is set. The environment of a
+//! running process cannot change, so the file is what makes
+//! `POST /config/reload` useful: edit it, then reload.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Serialize;
+
+pub const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
+pub const DEFAULT_CACHE_TTL_SECS: u64 = 300;
+/// Failures are cached briefly so a recovered upstream is noticed quickly.
+pub const DEFAULT_NEGATIVE_CACHE_TTL_SECS: u64 = 10;
+/// Total size of cached responses, measured as serialized JSON.
+pub const DEFAULT_CACHE_MAX_WEIGHT_BYTES: u64 = 64 * 1024 * 1024;
+pub const DEFAULT_MAX_WS_CONNECTIONS: usize = 50;
+pub const DEFAULT_WS_FILE_CHUNK_SIZE: usize = 64 * 1024;
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 10;
+pub const DEFAULT_MAX_BATCH_REQUESTS: usize = 1000;
+pub const DEFAULT_LOADTEST_TOTAL: usize = 100;
+pub const DEFAULT_MAX_LOADTEST_REQUESTS: usize = 10_000;
+pub const DEFAULT_MAX_OUTGOING_HEADERS: usize = 100;
+pub const DEFAULT_MAX_HEADER_VALUE_BYTES: usize = 8 * 1024;
+/// Decompressed bodies larger than this are treated as decompression bombs.
+pub const DEFAULT_MAX_DECOMPRESSED_BYTES: usize = 100 * 1024 * 1024;
+/// DNS lookups are not cached unless a TTL is configured.
+pub const DEFAULT_DNS_CACHE_TTL_SECS: u64 = 0;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct RuntimeConfig {
+    pub request_timeout_secs: u64,
+    pub cache_ttl_secs: u64,
+    pub negative_cache_ttl_secs: u64,
+    pub cache_max_weight_bytes: u64,
+    pub max_ws_connections: usize,
+    pub ws_file_chunk_size: usize,
+    pub batch_concurrency: usize,
+    pub max_batch_requests: usize,
+    pub loadtest_total: usize,
+    pub max_loadtest_requests: usize,
+    pub max_outgoing_headers: usize,
+    pub max_header_value_bytes: usize,
+    pub max_decompressed_bytes: usize,
+    pub dns_cache_ttl_secs: u64,
+    /// Expected `Content-Type` for requests that set no `assert_content_type`
+    /// of their own. Empty means no check.
+    pub assert_content_type: String,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout_secs: DEFAULT_REQUEST_TIMEOUT_SECS,
+            cache_ttl_secs: DEFAULT_CACHE_TTL_SECS,
+            negative_cache_ttl_secs: DEFAULT_NEGATIVE_CACHE_TTL_SECS,
+            cache_max_weight_bytes: DEFAULT_CACHE_MAX_WEIGHT_BYTES,
+            max_ws_connections: DEFAULT_MAX_WS_CONNECTIONS,
+            ws_file_chunk_size: DEFAULT_WS_FILE_CHUNK_SIZE,
+            batch_concurrency: DEFAULT_BATCH_CONCURRENCY,
+            max_batch_requests: DEFAULT_MAX_BATCH_REQUESTS,
+            loadtest_total: DEFAULT_LOADTEST_TOTAL,
+            max_loadtest_requests: DEFAULT_MAX_LOADTEST_REQUESTS,
+            max_outgoing_headers: DEFAULT_MAX_OUTGOING_HEADERS,
+            max_header_value_bytes: DEFAULT_MAX_HEADER_VALUE_BYTES,
+            max_decompressed_bytes: DEFAULT_MAX_DECOMPRESSED_BYTES,
+            dns_cache_ttl_secs: DEFAULT_DNS_CACHE_TTL_SECS,
+            assert_content_type: String::new(),
+        }
+    }
+}
+
+impl RuntimeConfig {
+    /// Reads the configuration. A value that is present but unparsable or out
+    /// of range is an error rather than silently falling back to its default.
+    pub fn load() -> Result<Self, String> {
+        let mut vars: HashMap<String, String> = std::env::vars().collect();
+        if let Ok(path) = std::env::var("CONFIG_FILE") {
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Cannot read CONFIG_FILE {}: {}", path, e))?;
+            vars.extend(parse_config_file(&contents));
+        }
+        Self::from_vars(&vars)
+    }
+
+    /// Zero is rejected wherever it would stall or refuse every request;
+    /// only `DNS_CACHE_TTL` uses it, to turn the DNS cache off.
+    fn from_vars(vars: &HashMap<String, String>) -> Result<Self, String> {
+        const DAY_SECS: u64 = 24 * 60 * 60;
+        const GIB: u64 = 1024 * 1024 * 1024;
+        let defaults = Self::default();
+        Ok(Self {
+            request_timeout_secs: setting(vars, "REQUEST_TIMEOUT_SECS", defaults.request_timeout_secs, 1..=3600)?,
+            cache_ttl_secs: setting(vars, "CACHE_TTL_SECS", defaults.cache_ttl_secs, 1..=7 * DAY_SECS)?,
+            negative_cache_ttl_secs: setting(
+                vars,
+                "NEGATIVE_CACHE_TTL_SECONDS",
+                defaults.negative_cache_ttl_secs,
+                1..=DAY_SECS,
+            )?,
+            cache_max_weight_bytes: setting(
+                vars,
+                "CACHE_MAX_WEIGHT_BYTES",
+                defaults.cache_max_weight_bytes,
+                1..=16 * GIB,
+            )?,
+            max_ws_connections: setting(vars, "MAX_WS_CONNECTIONS", defaults.max_ws_connections, 1..=100_000)?,
+            ws_file_chunk_size: setting(vars, "WS_FILE_CHUNK_SIZE", defaults.ws_file_chunk_size, 1..=16 << 20)?,
+            batch_concurrency: setting(vars, "BATCH_CONCURRENCY", defaults.batch_concurrency, 1..=10_000)?,
+            max_batch_requests: setting(vars, "MAX_BATCH_REQUESTS", defaults.max_batch_requests, 1..=1_000_000)?,
+            loadtest_total: setting(vars, "LOADTEST_TOTAL", defaults.loadtest_total, 1..=1_000_000)?,
+            max_loadtest_requests: setting(
+                vars,
+                "MAX_LOADTEST_REQUESTS",
+                defaults.max_loadtest_requests,
+                1..=1_000_000,
+            )?,
+            max_outgoing_headers: setting(vars, "MAX_OUTGOING_HEADERS", defaults.max_outgoing_headers, 1..=10_000)?,
+            max_header_value_bytes: setting(
+                vars,
+                "MAX_HEADER_VALUE_BYTES",
+                defaults.max_header_value_bytes,
+                1..=1 << 20,
+            )?,
+            max_decompressed_bytes: setting(
+                vars,
+                "MAX_DECOMPRESSED_BYTES",
+                defaults.max_decompressed_bytes,
+                1..=4 << 30,
+            )?,
+            dns_cache_ttl_secs: setting(vars, "DNS_CACHE_TTL", defaults.dns_cache_ttl_secs, 0..=DAY_SECS)?,
+            assert_content_type: text_setting(vars, "ASSERT_CONTENT_TYPE", defaults.assert_content_type),
+        })
+    }
+
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_secs(self.request_timeout_secs)
+    }
+
+    pub fn cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.cache_ttl_secs)
+    }
+
+    pub fn negative_cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.negative_cache_ttl_secs)
+    }
+
+    pub fn dns_cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.dns_cache_ttl_secs)
+    }
+}
+
+fn setting<T: FromStr + PartialOrd + Display>(
+    vars: &HashMap<String, String>,
+    key: &str,
+    default: T,
+    range: RangeInclusive<T>,
+) -> Result<T, String> {
+    let Some(value) = vars.get(key).map(|v| v.trim()).filter(|v| !v.is_empty()) else {
+        return Ok(default);
+    };
+    match value.parse() {
+        Ok(parsed) if range.contains(&parsed) => Ok(parsed),
+        _ => Err(format!(
+            "Invalid value for {}: '{}' (expected {} to {})",
+            key,
+            value,
+            range.start(),
+            range.end()
+        )),
+    }
+}
+
+fn text_setting(vars: &HashMap<String, String>, key: &str, default: String) -> String {
+    vars.get(key).map(|v| v.trim()).filter(|v| !v.is_empty()).map_or(default, str::to_string)
+}
+
+/// Parses `KEY=VALUE` lines, skipping blanks and `#` comments. Values may be
+/// wrapped in quotes.
+fn parse_config_file(contents: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    contents.lines().filter_map(|line| {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (key, value) = line.split_once('=')?;
+        let value = value.trim().trim_matches('"').trim_matches('\'');
+        Some((key.trim().to_string(), value.to_string()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn unset_and_blank_values_fall_back_to_defaults() {
+        let config = RuntimeConfig::from_vars(&vars(&[("CACHE_TTL_SECS", "  ")])).unwrap();
+        assert_eq!(config, RuntimeConfig::default());
+    }
+
+    #[test]
+    fn values_in_range_are_read() {
+        let config = RuntimeConfig::from_vars(&vars(&[
+            ("REQUEST_TIMEOUT_SECS", "5"),
+            ("MAX_WS_CONNECTIONS", " 3 "),
+            ("DNS_CACHE_TTL", "0"),
+            ("ASSERT_CONTENT_TYPE", "application/*json"),
+        ]))
+        .unwrap();
+        assert_eq!(config.request_timeout_secs, 5);
+        assert_eq!(config.max_ws_connections, 3);
+        assert_eq!(config.dns_cache_ttl_secs, 0);
+        assert_eq!(config.assert_content_type, "application/*json");
+    }
+
+    #[test]
+    fn zero_out_of_range_and_unparsable_values_are_rejected() {
+        for (key, value) in [
+            ("REQUEST_TIMEOUT_SECS", "0"),
+            ("MAX_WS_CONNECTIONS", "0"),
+            ("CACHE_TTL_SECS", "0"),
+            ("NEGATIVE_CACHE_TTL_SECONDS", "0"),
+            ("CACHE_MAX_WEIGHT_BYTES", "0"),
+            ("BATCH_CONCURRENCY", "0"),
+            ("REQUEST_TIMEOUT_SECS", "86400"),
+            ("REQUEST_TIMEOUT_SECS", "-1"),
+            ("MAX_WS_CONNECTIONS", "many"),
+        ] {
+            let error = RuntimeConfig::from_vars(&vars(&[(key, value)])).unwrap_err();
+            assert!(error.contains(key), "{}={} gave: {}", key, value, error);
+        }
+    }
+
+    #[test]
+    fn config_file_lines_are_parsed() {
+        let parsed: HashMap<String, String> =
+            parse_config_file("# comment\n\nREQUEST_TIMEOUT_SECS = 12\nASSERT_CONTENT_TYPE=\"text/plain\"\n").collect();
+        let config = RuntimeConfig::from_vars(&parsed).unwrap();
+        assert_eq!(config.request_timeout_secs, 12);
+        assert_eq!(config.assert_content_type, "text/plain");
+    }
+}
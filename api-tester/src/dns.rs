@@ -0,0 +1,97 @@
+//! An in-process DNS cache used as the resolver for the outgoing clients.
+//!
+//! Lookups go through the system resolver like reqwest's default, but the
+//! addresses are kept for `DNS_CACHE_TTL` seconds. A TTL of 0 disables the
+//! cache. `DELETE /dns-cache` flushes it so DNS-based failover can be tested
+//! on demand instead of waiting for entries to expire.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+
+struct Entry {
+    addrs: Vec<SocketAddr>,
+    resolved_at: Instant,
+}
+
+pub struct DnsCache {
+    ttl_secs: AtomicU64,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl DnsCache {
+    pub fn new(ttl: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            ttl_secs: AtomicU64::new(ttl.as_secs()),
+            entries: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_secs.load(Ordering::Relaxed))
+    }
+
+    pub fn set_ttl(&self, ttl: Duration) {
+        self.ttl_secs.store(ttl.as_secs(), Ordering::Relaxed);
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.ttl().is_zero()
+    }
+
+    /// Addresses for `host` if it was resolved within the TTL.
+    fn fresh(&self, host: &str) -> Option<Vec<SocketAddr>> {
+        let ttl = self.ttl();
+        self.entries
+            .lock()
+            .unwrap()
+            .get(host)
+            .filter(|entry| !ttl.is_zero() && entry.resolved_at.elapsed() < ttl)
+            .map(|entry| entry.addrs.clone())
+    }
+
+    /// True when a lookup of `host` right now would be answered from the cache.
+    pub fn contains(&self, host: &str) -> bool {
+        self.fresh(host).is_some()
+    }
+
+    /// Drops every entry, returning how many there were.
+    pub fn flush(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let count = entries.len();
+        entries.clear();
+        count
+    }
+
+    pub fn resolver(self: &Arc<Self>) -> Arc<CachingResolver> {
+        Arc::new(CachingResolver(self.clone()))
+    }
+}
+
+pub struct CachingResolver(Arc<DnsCache>);
+
+impl Resolve for CachingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let cache = self.0.clone();
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            if let Some(addrs) = cache.fresh(&host) {
+                return Ok(Box::new(addrs.into_iter()) as Addrs);
+            }
+            // The port is a placeholder; the connector substitutes the real one.
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if cache.enabled() {
+                cache.entries.lock().unwrap().insert(
+                    host,
+                    Entry { addrs: addrs.clone(), resolved_at: Instant::now() },
+                );
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
@@ -0,0 +1,54 @@
+//! An inventory of the upstream hosts this process has sent requests to,
+//! served by `GET /hosts`. Hosts are keyed by name plus any explicit port,
+//! so `api.example.com` and `api.example.com:8443` are listed separately.
+//! The inventory lives in memory and is never pruned.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use url::Url;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct HostStats {
+    pub host: String,
+    pub requests: u64,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+#[derive(Default)]
+pub struct HostStore {
+    hosts: Mutex<HashMap<String, HostStats>>,
+}
+
+impl HostStore {
+    /// Counts a request to the host of `url` and returns how many distinct
+    /// hosts have been seen. URLs without a host are ignored.
+    pub fn record(&self, url: &Url) -> usize {
+        let mut hosts = self.hosts.lock().unwrap();
+        if let Some(host) = url.host_str() {
+            let host = match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_string(),
+            };
+            let now = chrono::Utc::now().to_rfc3339();
+            let stats = hosts.entry(host.clone()).or_insert_with(|| HostStats {
+                host,
+                requests: 0,
+                first_seen: now.clone(),
+                last_seen: now.clone(),
+            });
+            stats.requests += 1;
+            stats.last_seen = now;
+        }
+        hosts.len()
+    }
+
+    /// Every host seen, busiest first.
+    pub fn list(&self) -> Vec<HostStats> {
+        let mut hosts: Vec<HostStats> = self.hosts.lock().unwrap().values().cloned().collect();
+        hosts.sort_by(|a, b| b.requests.cmp(&a.requests).then_with(|| a.host.cmp(&b.host)));
+        hosts
+    }
+}
@@ -0,0 +1,86 @@
+//! Global rules applied to every proxied request, configured at runtime via
+//! `POST /interceptors`. Request-side rules run in order just before the
+//! request is sent; response-side rules run in order once the body is parsed.
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use url::Url;
+
+use crate::jsonpath;
+
+/// The `type` tags accepted by `POST /interceptors`.
+pub const KINDS: [&str; 4] = ["set_header", "remove_header", "add_query_param", "redact_field"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Interceptor {
+    /// Sets a request header, replacing any value already present.
+    SetHeader { name: String, value: String },
+    RemoveHeader { name: String },
+    AddQueryParam { name: String, value: String },
+    /// Replaces every value matched by a JSONPath in the response body.
+    RedactField {
+        path: String,
+        #[serde(default = "default_redaction")]
+        replacement: serde_json::Value,
+    },
+}
+
+pub fn default_redaction() -> serde_json::Value {
+    serde_json::Value::String("[REDACTED]".to_string())
+}
+
+impl Interceptor {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Interceptor::SetHeader { name, value } => {
+                HeaderName::from_str(name).map_err(|_| format!("Invalid header name: {}", name))?;
+                HeaderValue::from_str(value).map_err(|_| format!("Invalid value for header {}", name))?;
+            }
+            Interceptor::RemoveHeader { name } => {
+                HeaderName::from_str(name).map_err(|_| format!("Invalid header name: {}", name))?;
+            }
+            Interceptor::AddQueryParam { .. } => {}
+            Interceptor::RedactField { path, .. } => {
+                jsonpath::parse(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Applies the request-side interceptors to the outgoing headers and URL.
+pub fn apply_request(interceptors: &[Interceptor], headers: &mut HeaderMap, url: &mut String) {
+    for interceptor in interceptors {
+        match interceptor {
+            Interceptor::SetHeader { name, value } => {
+                if let (Ok(name), Ok(value)) = (HeaderName::from_str(name), HeaderValue::from_str(value)) {
+                    headers.insert(name, value);
+                }
+            }
+            Interceptor::RemoveHeader { name } => {
+                headers.remove(name.as_str());
+            }
+            Interceptor::AddQueryParam { name, value } => {
+                // An unparsable URL is left alone; the send reports the error.
+                if let Ok(mut parsed) = Url::parse(url) {
+                    parsed.query_pairs_mut().append_pair(name, value);
+                    *url = parsed.into();
+                }
+            }
+            Interceptor::RedactField { .. } => {}
+        }
+    }
+}
+
+/// Applies the response-side interceptors to a parsed response body.
+pub fn apply_response(interceptors: &[Interceptor], body: &mut serde_json::Value) {
+    for interceptor in interceptors {
+        if let Interceptor::RedactField { path, replacement } = interceptor {
+            if let Ok(segments) = jsonpath::parse(path) {
+                jsonpath::replace_all(body, &segments, replacement);
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! One-shot jobs scheduled via `POST /schedule-once`: a request that runs
+//! once after a delay, with its outcome kept for `GET /jobs/{id}`.
+//!
+//! Jobs live in memory. Finished jobs are removed by `sweep` after
+//! `JOB_RETENTION`; pending jobs are kept until they run.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+pub const JOB_RETENTION: Duration = Duration::from_secs(3600);
+/// The furthest in the future a job may be scheduled.
+pub const MAX_SCHEDULE_DELAY: Duration = Duration::from_secs(24 * 3600);
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobStatus<T> {
+    Pending,
+    Running,
+    Completed { result: T },
+    Failed { error: String },
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Job<T> {
+    pub job_id: String,
+    pub run_at: String,
+    #[serde(flatten)]
+    pub status: JobStatus<T>,
+    #[serde(skip)]
+    finished: Option<Instant>,
+}
+
+pub struct JobStore<T> {
+    jobs: Mutex<HashMap<String, Job<T>>>,
+}
+
+impl<T> Default for JobStore<T> {
+    fn default() -> Self {
+        Self { jobs: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl<T: Clone> JobStore<T> {
+    pub fn add(&self, job_id: String, run_at: String) -> Job<T> {
+        let job = Job { job_id: job_id.clone(), run_at, status: JobStatus::Pending, finished: None };
+        self.jobs.lock().unwrap().insert(job_id, job.clone());
+        job
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<Job<T>> {
+        self.jobs.lock().unwrap().get(job_id).cloned()
+    }
+
+    pub fn set_status(&self, job_id: &str, status: JobStatus<T>) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(job_id) {
+            if matches!(status, JobStatus::Completed { .. } | JobStatus::Failed { .. }) {
+                job.finished = Some(Instant::now());
+            }
+            job.status = status;
+        }
+    }
+
+    /// Drops jobs that finished more than `JOB_RETENTION` ago.
+    pub fn sweep(&self) {
+        self.jobs
+            .lock()
+            .unwrap()
+            .retain(|_, job| job.finished.is_none_or(|at| at.elapsed() < JOB_RETENTION));
+    }
+}
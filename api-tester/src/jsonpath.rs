@@ -0,0 +1,163 @@
+//! A small JSONPath subset used to pick values out of response bodies.
+//!
+//! Supported syntax: an optional leading `$`, dotted keys (`.user.name`),
+//! bracketed keys (`['content-type']` or `["content-type"]`), array indices
+//! (`[0]`, `[-1]` for the last element) and wildcards (`.*` or `[*]`).
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Key(String),
+    Index(i64),
+    Wildcard,
+}
+
+pub fn parse(path: &str) -> Result<Vec<Segment>, String> {
+    let path = path.trim();
+    let rest = path.strip_prefix('$').unwrap_or(path);
+    let chars: Vec<char> = rest.chars().collect();
+    let mut segments = Vec::new();
+    let mut i = 0;
+
+    // Allow a bare leading key such as `data.items` in addition to `$.data.items`.
+    if !chars.is_empty() && chars[0] != '.' && chars[0] != '[' {
+        let (key, next) = read_key(&chars, 0);
+        segments.push(key_segment(key));
+        i = next;
+    }
+
+    while i < chars.len() {
+        match chars[i] {
+            '.' => {
+                let (key, next) = read_key(&chars, i + 1);
+                if key.is_empty() {
+                    return Err(format!("Empty key in path '{}'", path));
+                }
+                segments.push(key_segment(key));
+                i = next;
+            }
+            '[' => {
+                let close = chars[i..]
+                    .iter()
+                    .position(|&c| c == ']')
+                    .map(|p| p + i)
+                    .ok_or_else(|| format!("Unclosed '[' in path '{}'", path))?;
+                let inner: String = chars[i + 1..close].iter().collect();
+                let inner = inner.trim();
+                if inner == "*" {
+                    segments.push(Segment::Wildcard);
+                } else if let Some(quoted) = strip_quotes(inner) {
+                    segments.push(Segment::Key(quoted.to_string()));
+                } else {
+                    let index = inner
+                        .parse::<i64>()
+                        .map_err(|_| format!("Invalid index '{}' in path '{}'", inner, path))?;
+                    segments.push(Segment::Index(index));
+                }
+                i = close + 1;
+            }
+            c => return Err(format!("Unexpected '{}' in path '{}'", c, path)),
+        }
+    }
+
+    Ok(segments)
+}
+
+fn read_key(chars: &[char], start: usize) -> (String, usize) {
+    let end = chars[start..]
+        .iter()
+        .position(|&c| c == '.' || c == '[')
+        .map_or(chars.len(), |p| p + start);
+    (chars[start..end].iter().collect(), end)
+}
+
+fn key_segment(key: String) -> Segment {
+    if key == "*" {
+        Segment::Wildcard
+    } else {
+        Segment::Key(key)
+    }
+}
+
+fn strip_quotes(s: &str) -> Option<&str> {
+    s.strip_prefix('\'')
+        .and_then(|s| s.strip_suffix('\''))
+        .or_else(|| s.strip_prefix('"').and_then(|s| s.strip_suffix('"')))
+}
+
+/// Returns every value matched by `segments`, in document order.
+pub fn select_all<'a>(value: &'a Value, segments: &[Segment]) -> Vec<&'a Value> {
+    let mut current = vec![value];
+    for segment in segments {
+        let mut next = Vec::new();
+        for v in current {
+            match (segment, v) {
+                (Segment::Key(key), Value::Object(map)) => next.extend(map.get(key)),
+                (Segment::Index(index), Value::Array(items)) => {
+                    let resolved = if *index < 0 {
+                        items.len() as i64 + index
+                    } else {
+                        *index
+                    };
+                    if resolved >= 0 {
+                        next.extend(items.get(resolved as usize));
+                    }
+                }
+                (Segment::Wildcard, Value::Array(items)) => next.extend(items.iter()),
+                (Segment::Wildcard, Value::Object(map)) => next.extend(map.values()),
+                _ => {}
+            }
+        }
+        current = next;
+    }
+    current
+}
+
+/// Overwrites every value matched by `segments` with `replacement`. Returns
+/// the number of values replaced.
+pub fn replace_all(value: &mut Value, segments: &[Segment], replacement: &Value) -> usize {
+    let Some((segment, rest)) = segments.split_first() else {
+        *value = replacement.clone();
+        return 1;
+    };
+    match (segment, value) {
+        (Segment::Key(key), Value::Object(map)) => map
+            .get_mut(key)
+            .map_or(0, |v| replace_all(v, rest, replacement)),
+        (Segment::Index(index), Value::Array(items)) => {
+            let resolved = if *index < 0 {
+                items.len() as i64 + index
+            } else {
+                *index
+            };
+            if resolved < 0 {
+                return 0;
+            }
+            items
+                .get_mut(resolved as usize)
+                .map_or(0, |v| replace_all(v, rest, replacement))
+        }
+        (Segment::Wildcard, Value::Array(items)) => items
+            .iter_mut()
+            .map(|v| replace_all(v, rest, replacement))
+            .sum(),
+        (Segment::Wildcard, Value::Object(map)) => map
+            .values_mut()
+            .map(|v| replace_all(v, rest, replacement))
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// Evaluates `path` against `value`. Paths containing a wildcard yield an
+/// array of all matches; other paths yield the single match or `None`.
+pub fn query(value: &Value, path: &str) -> Result<Option<Value>, String> {
+    let segments = parse(path)?;
+    let matches = select_all(value, &segments);
+    if segments.contains(&Segment::Wildcard) {
+        Ok(Some(Value::Array(matches.into_iter().cloned().collect())))
+    } else {
+        Ok(matches.first().map(|v| (*v).clone()))
+    }
+}
@@ -0,0 +1,112 @@
+//! Decoding of JSON Web Tokens found in response bodies, for `decode_jwt`.
+//!
+//! Only the header and claims are decoded. The signature is not verified,
+//! so the result says what the token claims, not that the claims are true.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DecodedJwt {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub claims: Option<Value>,
+    /// The `exp` claim as an RFC 3339 timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expired: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Decodes `token`, which may carry a `Bearer ` prefix. Problems are
+/// reported in `error` rather than failing the request.
+pub fn decode(token: &str, now: DateTime<Utc>) -> DecodedJwt {
+    match decode_parts(token) {
+        Ok((header, claims)) => {
+            let expires_at = claims
+                .get("exp")
+                .and_then(Value::as_i64)
+                .and_then(|exp| DateTime::<Utc>::from_timestamp(exp, 0));
+            DecodedJwt {
+                header: Some(header),
+                claims: Some(claims),
+                expires_at: expires_at.map(|at| at.to_rfc3339()),
+                expired: expires_at.map(|at| at <= now),
+                error: None,
+            }
+        }
+        Err(e) => DecodedJwt { error: Some(e), ..DecodedJwt::default() },
+    }
+}
+
+fn decode_parts(token: &str) -> Result<(Value, Value), String> {
+    let token = token.trim();
+    let token = token.strip_prefix("Bearer ").unwrap_or(token).trim();
+    let parts: Vec<&str> = token.split('.').collect();
+    let [header, claims, _signature] = parts.as_slice() else {
+        return Err(format!("Expected 3 dot-separated parts, found {}", parts.len()));
+    };
+    Ok((decode_segment(header, "header")?, decode_segment(claims, "claims")?))
+}
+
+fn decode_segment(segment: &str, what: &str) -> Result<Value, String> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(segment.trim_end_matches('='))
+        .map_err(|e| format!("Invalid base64url in {}: {}", what, e))?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("Invalid JSON in {}: {}", what, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn segment(value: &Value) -> String {
+        URL_SAFE_NO_PAD.encode(value.to_string())
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::<Utc>::from_timestamp(1_700_000_000, 0).unwrap()
+    }
+
+    #[test]
+    fn decodes_header_claims_and_expiry() {
+        let token = format!(
+            "Bearer {}.{}.sig",
+            segment(&json!({"alg": "HS256"})),
+            segment(&json!({"sub": "ada", "exp": 1_600_000_000}))
+        );
+        let decoded = decode(&token, now());
+        assert!(decoded.error.is_none(), "{:?}", decoded.error);
+        assert_eq!(decoded.header, Some(json!({"alg": "HS256"})));
+        assert_eq!(decoded.claims.unwrap()["sub"], "ada");
+        assert_eq!(decoded.expired, Some(true));
+    }
+
+    #[test]
+    fn rejects_tokens_without_three_parts() {
+        for token in ["", "abc", "abc.def", "a.b.c.d"] {
+            let error = decode(token, now()).error.unwrap();
+            assert!(error.starts_with("Expected 3 dot-separated parts"), "{}: {}", token, error);
+        }
+    }
+
+    #[test]
+    fn rejects_segments_that_are_not_base64url_json() {
+        let claims = segment(&json!({"sub": "ada"}));
+        // `+` and `/` belong to standard base64, not base64url.
+        let error = decode(&format!("ab+/.{}.sig", claims), now()).error.unwrap();
+        assert!(error.starts_with("Invalid base64url in header"), "{}", error);
+
+        let not_json = URL_SAFE_NO_PAD.encode("not json");
+        let error = decode(&format!("{}.{}.sig", segment(&json!({})), not_json), now()).error.unwrap();
+        assert!(error.starts_with("Invalid JSON in claims"), "{}", error);
+        assert!(decode(&format!("{}.{}.sig", segment(&json!({})), not_json), now()).claims.is_none());
+    }
+}
@@ -0,0 +1,40 @@
+//! Logger setup. Records go through the usual `RUST_LOG` filter, except
+//! those logged to `VERBOSE_TARGET`, which `verbose` requests use so their
+//! detail shows up without lowering the level for everything else.
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Target for per-request detail; logged at info whatever `RUST_LOG` says.
+pub const VERBOSE_TARGET: &str = "api_tester::verbose";
+
+struct ScopedLogger {
+    global: env_logger::Logger,
+    verbose: env_logger::Logger,
+}
+
+impl Log for ScopedLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.global.enabled(metadata) || self.verbose.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.verbose.matches(record) {
+            self.verbose.log(record);
+        } else {
+            self.global.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.global.flush();
+    }
+}
+
+/// Installs the logger, with `RUST_LOG` defaulting to `info`.
+pub fn init() {
+    let global = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).build();
+    let verbose = env_logger::Builder::new().filter(Some(VERBOSE_TARGET), LevelFilter::Info).build();
+    let max_level = global.filter().max(verbose.filter());
+    log::set_boxed_logger(Box::new(ScopedLogger { global, verbose })).expect("logger is installed once");
+    log::set_max_level(max_level);
+}
@@ -1,23 +1,64 @@
 use actix_web::{web, App, HttpServer, HttpResponse, get};
+use actix_web::http::StatusCode;
 use actix_cors::Cors;
 use serde::{Deserialize, Serialize};
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, ACCEPT, ACCEPT_ENCODING, AGE, CACHE_CONTROL, CONTENT_ENCODING,
+    CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, DATE, ETAG, LAST_MODIFIED, LOCATION, RANGE,
+};
 use tokio_tungstenite::connect_async;
-use std::collections::HashMap;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 use futures_util::{SinkExt, StreamExt};
-use log::{info, error};
-use moka::future::Cache;
+use log::{info, warn, error};
+use tokio::sync::{mpsc, Notify, OwnedSemaphorePermit, Semaphore};
 use chrono::Utc;
+use base64::engine::general_purpose::{STANDARD as BASE64, URL_SAFE};
+use base64::Engine;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use lazy_static::lazy_static;
 use prometheus::{
-    IntCounter, IntGauge, Histogram,
-    IntCounterVec, register_int_counter_vec, register_histogram, 
+    IntCounter, IntGauge, Histogram, HistogramVec,
+    IntCounterVec, register_int_counter_vec, register_histogram, register_histogram_vec,
     register_int_counter, register_int_gauge
 };
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use regex::Regex;
+use sha2::{Digest, Sha256};
 use url::Url;
 
+mod assertions;
+mod baselines;
+mod cache;
+mod chaos;
+mod config;
+mod dns;
+mod hosts;
+mod interceptors;
+mod jobs;
+mod jsonpath;
+mod jwt;
+mod logging;
+mod multipart;
+mod openapi;
+mod rawhttp;
+mod runs;
+mod sse;
+mod tls;
+mod transforms;
+mod uploads;
+mod wwwauth;
+
 lazy_static! {
     static ref HTTP_REQUESTS_TOTAL: IntCounterVec = register_int_counter_vec!(
         "http_requests_total",
@@ -39,27 +80,354 @@ lazy_static! {
         "active_requests",
         "Number of requests currently being processed"
     ).unwrap();
+
+    static ref ACTIVE_WS_CONNECTIONS: IntGauge = register_int_gauge!(
+        "active_ws_connections",
+        "Number of WebSocket connections currently open"
+    ).unwrap();
+
+    static ref LOGICAL_REQUESTS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "logical_requests_total",
+        "Upstream responses judged by a request's success_criteria",
+        &["outcome"]
+    ).unwrap();
+
+    static ref UPSTREAM_HOSTS_SEEN: IntGauge = register_int_gauge!(
+        "upstream_hosts_seen",
+        "Number of distinct upstream hosts requests have been sent to"
+    ).unwrap();
+
+    static ref RUN_REQUESTS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "run_requests_total",
+        "Requests made as part of a named test run",
+        &["run_id", "status"]
+    ).unwrap();
+
+    static ref RUN_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "run_request_duration_seconds",
+        "Upstream request duration within a named test run",
+        &["run_id"]
+    ).unwrap();
 }
 
-const CACHE_MAX_CAPACITY: u64 = 1000;
-const CACHE_TIME_TO_LIVE: Duration = Duration::from_secs(300); 
-const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const REDIRECT_STATUSES: [u16; 5] = [301, 302, 303, 307, 308];
+const DEFAULT_MAX_PAGES: usize = 10;
+const MAX_PAGES_LIMIT: usize = 100;
+const DEFAULT_WS_BENCH_MESSAGES: usize = 100;
+const MAX_WS_BENCH_MESSAGES: usize = 10_000;
+const WS_BENCH_REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_WS_STEP_TIMEOUT_MS: u64 = 5_000;
+/// Events held between the upstream reader and the caller of `/sse`. Once
+/// it is full the reader stops pulling from upstream until the caller
+/// catches up.
+const SSE_EVENT_BUFFER: usize = 64;
+const MAX_INJECTED_DELAY_MS: u64 = 60_000;
+/// How long a reply waits past the response for a `probe_tls` handshake.
+const TLS_PROBE_WAIT: Duration = Duration::from_secs(2);
+/// The slowest `max_download_bps` accepted, so a typo cannot hold a request
+/// open for hours.
+const MIN_DOWNLOAD_BPS: u64 = 1024;
+/// The longest `timeout_ms` a single proxy request may ask for.
+const MAX_REQUEST_TIMEOUT_MS: u64 = 300_000;
+const DEFAULT_BASELINE_SAMPLES: usize = 10;
+const MAX_BASELINE_SAMPLES: usize = 100;
+const SUPPORTED_METHODS: [&str; 7] = ["GET", "POST", "PUT", "DELETE", "PATCH", "HEAD", "OPTIONS"];
+const REQUEST_COMPRESSIONS: [&str; 2] = ["gzip", "br"];
+const REQUEST_BODY_TYPES: [&str; 5] = ["json", "form", "text", "base64", "multipart"];
+/// Values the proxy reports in a response's `body_type`.
+const RESPONSE_BODY_TYPES: [&str; 4] = ["json", "text", "base64", "multipart"];
+/// Request flags that reshape the upstream body before it is returned.
+const BODY_SHAPING_OPTIONS: [&str; 7] =
+    ["select", "sort_keys", "repair_json", "hash_body", "raw_body", "raw_response", "to_csv"];
+const RESPONSE_ENCODINGS: [&str; 5] = ["gzip", "x-gzip", "deflate", "br", "identity"];
+const MIN_TLS_VERSIONS: [&str; 4] = ["1.0", "1.1", "1.2", "1.3"];
+/// `http_version` values the proxy can force. HTTP/3 is not among them yet:
+/// reqwest's QUIC support is unstable and needs h3 crates this build does not
+/// pull in, so `3` is refused with an explanatory error instead.
+const HTTP_VERSIONS: [&str; 2] = ["1.1", "2"];
+const CONVERT_ENCODINGS: [&str; 3] = ["utf8", "hex", "base64"];
+const PATCH_TYPES: [&str; 2] = ["json-patch", "merge-patch"];
+/// Operations allowed in an RFC 6902 JSON Patch document.
+const JSON_PATCH_OPS: [&str; 6] = ["add", "remove", "replace", "move", "copy", "test"];
 
 #[derive(Debug, Deserialize, Clone)]
 struct ProxyRequest {
+    /// Required unless `targets` is given.
+    #[serde(default)]
     url: String,
     method: String,
     headers: Option<HashMap<String, String>>,
     body: Option<serde_json::Value>,
+    /// How `body` is sent: `json` (the default), `form`, `text`, `base64` or
+    /// `multipart`. See `encode_request_body`.
+    body_type: Option<String>,
     #[serde(default)]
     use_cache: bool,
+    compress_request: Option<String>,
+    select: Option<Vec<String>>,
+    #[serde(default)]
+    allow_oversized_headers: bool,
+    #[serde(default)]
+    hash_body: bool,
+    echo_request: Option<bool>,
+    raw_body: Option<bool>,
+    min_tls_version: Option<String>,
+    /// Reports `tls_version` and `cipher_suite` from a separate handshake
+    /// with the host, run alongside the request. The reply waits up to
+    /// `TLS_PROBE_WAIT` after the body is read for it to finish.
+    probe_tls: Option<bool>,
+    /// Forces `1.1` or `2` instead of negotiating. `2` uses prior knowledge,
+    /// so it also works against cleartext h2c servers. `3` is not supported
+    /// yet; `h3_advertised` shows whether a server offers it.
+    http_version: Option<String>,
+    cancel_token: Option<String>,
+    #[serde(default)]
+    passthrough_status: bool,
+    success_statuses: Option<Vec<u16>>,
+    repair_json: Option<bool>,
+    tags: Option<Vec<String>>,
+    /// When `false`, the path is sent exactly as written instead of being
+    /// normalized and percent-encoded. Cannot be combined with `body_upload_id`.
+    encode_path: Option<bool>,
+    /// Sorts object keys in the body and hashes that canonical form, so the
+    /// output and `body_hash` do not depend on the server's key order.
+    sort_keys: Option<bool>,
+    /// Overrides `REQUEST_TIMEOUT_SECS` for this request, up to
+    /// `MAX_REQUEST_TIMEOUT_MS`.
+    timeout_ms: Option<u64>,
+    /// Throttles the body download to roughly this many bytes per second.
+    /// Must be at least `MIN_DOWNLOAD_BPS`.
+    max_download_bps: Option<u64>,
+    /// Statuses written to the cache when `use_cache` is set. Defaults to 2xx.
+    cacheable_statuses: Option<Vec<u16>>,
+    /// With `use_cache`, also caches 5xx responses and connection failures
+    /// for `NEGATIVE_CACHE_TTL_SECONDS`.
+    cache_failures: Option<bool>,
+    range: Option<ByteRange>,
+    trace: Option<bool>,
+    /// Adds a `Server-Timing` header with the measured `total`, `ttfb` and,
+    /// when known, `dns` timings to the `/proxy` reply, for browser devtools.
+    emit_server_timing: Option<bool>,
+    /// Logs the request as sent and the response as received, with
+    /// sensitive headers masked, at info level whatever `RUST_LOG` says.
+    verbose: Option<bool>,
+    /// Reports the configuration the request ran with in `config_trace`.
+    debug_config: Option<bool>,
+    /// Like `echo_request`, but also returns the exact body bytes as base64.
+    capture_request: Option<bool>,
+    /// Follows `Link: <...>; rel="next"` headers and aggregates the pages.
+    follow_link_pagination: Option<bool>,
+    max_pages: Option<usize>,
+    /// Streams a file previously posted to `/upload` as the request body.
+    body_upload_id: Option<String>,
+    /// A binary request body, base64-encoded. Sent as
+    /// `application/octet-stream` unless `headers` sets a content type.
+    body_base64: Option<String>,
+    /// Makes `/proxy` return the upstream status, content type and body bytes
+    /// as-is instead of the JSON envelope. Ignored by `/batch`.
+    raw_response: Option<bool>,
+    /// Makes `/proxy` return the shaped body as `text/csv`. The body must be
+    /// a JSON array of objects. Ignored by `/batch`.
+    to_csv: Option<bool>,
+    /// Sends `Expect: 100-continue` and holds the body back until the server
+    /// answers `100 Continue`, reporting the outcome as `expect_continue`.
+    expect_continue: Option<bool>,
+    /// An ordered list of body transforms, applied after `sort_keys` and
+    /// `select`. See the `transforms` module for the semantics.
+    transforms: Option<Vec<transforms::Transform>>,
+    /// Sleeps this long before sending upstream. Not counted in `duration_ms`.
+    pre_delay_ms: Option<u64>,
+    /// Sleeps this long after the upstream response, before replying.
+    post_delay_ms: Option<u64>,
+    /// Compares `duration_ms` against a recorded latency baseline.
+    check_latency_baseline: Option<LatencyBaselineCheck>,
+    /// A pool of URLs to pick from, by weight, each time the request runs.
+    /// Used instead of `url`; the pick is reported as `target`.
+    targets: Option<Vec<WeightedTarget>>,
+    /// Seeds the choice among `targets`, so the same seed always picks the
+    /// same target.
+    random_seed: Option<u64>,
+    /// Sends a generated request id and reports whether the response echoes
+    /// it back.
+    check_request_id: Option<RequestIdCheck>,
+    /// Checks on the response body, reported in `assertion_results`. See the
+    /// `assertions` module for the operands and comparisons.
+    assertions: Option<Vec<assertions::Assertion>>,
+    /// Checks on the cookies the response sets, reported in
+    /// `cookie_assertion_results`.
+    cookie_assertions: Option<Vec<assertions::CookieAssertion>>,
+    /// Counts the request towards a run started with `POST /run/start`.
+    run_id: Option<String>,
+    /// JSONPath to a JWT in the response body to decode into `decoded_jwt`.
+    decode_jwt: Option<String>,
+    /// Expected media type of the response, reported as `content_type_ok`.
+    /// `*` matches any run of characters, e.g. `application/*json`. Overrides
+    /// the `ASSERT_CONTENT_TYPE` default; an empty string skips the check.
+    assert_content_type: Option<String>,
+    /// Checks whether the response carried a compression `Content-Encoding`,
+    /// reported as `compression_ok`. `false` expects an uncompressed
+    /// response. Like `raw_body`, advertises `Accept-Encoding` unless
+    /// `headers` sets one, and decodes the body before parsing it.
+    expect_compressed: Option<bool>,
+    /// Also returns the headers as `[name, value]` pairs in `ordered_headers`,
+    /// keeping their order and any repeated names.
+    ordered_headers: Option<bool>,
+    /// Decides whether the request counts as a success in
+    /// `logical_requests_total`, for APIs that report errors in a 2xx body.
+    success_criteria: Option<SuccessCriteria>,
+    /// Sends a PATCH body as `json-patch` (RFC 6902) or `merge-patch`
+    /// (RFC 7386), with the matching content type unless `headers` sets one.
+    /// The body is checked against the format before sending.
+    patch_type: Option<String>,
+    /// Fails `age_ok` when the response is older than this, judged by its
+    /// `Age` header or else its `Date`. A response with neither fails.
+    max_age_seconds: Option<u64>,
+    /// For GET requests, checks that the response advertises caching through
+    /// `Cache-Control` max-age, `ETag` or `Last-Modified`, reported as
+    /// `cacheable`.
+    require_cacheable: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct SuccessCriteria {
+    path: String,
+    equals: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct RequestIdCheck {
+    #[serde(default = "default_request_id_header")]
+    header: String,
+    /// The response header expected to carry the id. Defaults to `header`.
+    echo_header: Option<String>,
+}
+
+fn default_request_id_header() -> String {
+    "X-Request-Id".to_string()
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct WeightedTarget {
+    url: String,
+    weight: f64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct LatencyBaselineCheck {
+    name: String,
+    /// Allowed standard deviations above the baseline mean.
+    stddevs: Option<f64>,
+    /// Allowed percentage above the baseline mean.
+    percent: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecordBaselineRequest {
+    request: ProxyRequest,
+    samples: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MultiRegionRequest {
+    /// The request to send to every region. Its `url` is a path appended to
+    /// each region's `base_url`.
+    request: ProxyRequest,
+    regions: Vec<Region>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Region {
+    /// Defaults to `base_url`.
+    name: Option<String>,
+    base_url: String,
+}
+
+/// An inclusive byte range sent as `Range: bytes=start-end`. Without `end`
+/// the range runs to the end of the resource.
+#[derive(Debug, Deserialize, Clone)]
+struct ByteRange {
+    start: u64,
+    end: Option<u64>,
+}
+
+impl ByteRange {
+    fn header_value(&self) -> String {
+        match self.end {
+            Some(end) => format!("bytes={}-{}", self.start, end),
+            None => format!("bytes={}-", self.start),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenApiDiffRequest {
+    old: serde_json::Value,
+    new: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenApiImportRequest {
+    /// The spec, as a JSON object or as JSON/YAML text.
+    spec: serde_json::Value,
+    /// Overrides the server URL declared in the spec.
+    base_url: Option<String>,
+    /// Parameter values by name, used before the spec's own examples.
+    #[serde(default)]
+    parameters: HashMap<String, String>,
+    headers: Option<HashMap<String, String>>,
+    concurrency: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchRequest {
+    requests: Vec<ProxyRequest>,
+    concurrency: Option<usize>,
+    /// Runs only the requests carrying at least one of these tags.
+    only_tags: Option<Vec<String>>,
+    /// Seeds target selection across the batch: the request at index `i`
+    /// without a `random_seed` of its own uses this seed plus `i`, so a rerun
+    /// picks the same targets whatever order the requests complete in.
+    random_seed: Option<u64>,
+    /// Returns only this fraction (0 to 1) of the passing results, picked at
+    /// random, while keeping every failure. The counts still cover the whole
+    /// batch. Ignored by `/batch/stream`.
+    sample_rate: Option<f64>,
 }
 
 #[derive(Debug, Deserialize)]
 struct WebSocketRequest {
     url: String,
+    #[serde(default)]
     messages: Vec<String>,
     duration: Option<u64>,
+    send_file: Option<String>,
+    chunk_size: Option<usize>,
+    #[serde(default)]
+    binary_frames: bool,
+    /// A conversation run after `messages`: each step sends its message and
+    /// waits for the reply before the next step is sent.
+    steps: Option<Vec<WebSocketStep>>,
+    /// Checks whether the server agrees to `permessage-deflate`. The client
+    /// cannot inflate frames, so this is tested on a separate handshake and
+    /// the session itself stays uncompressed.
+    enable_compression: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebSocketStep {
+    send: String,
+    /// A regex the reply must match; frames that do not match are logged and
+    /// skipped. Without it, the next text frame is the reply.
+    wait_for: Option<String>,
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebSocketBenchRequest {
+    url: String,
+    message_count: Option<usize>,
+    /// Pause between receiving a reply and sending the next message.
+    interval_ms: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -70,14 +438,377 @@ struct GraphQLRequest {
     headers: Option<HashMap<String, String>>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Deserialize)]
+struct ScheduleOnceRequest {
+    request: ProxyRequest,
+    /// Runs the request this long from now. Exclusive with `run_at`.
+    delay_ms: Option<u64>,
+    /// Runs the request at this RFC 3339 time; a time in the past runs it
+    /// immediately.
+    run_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DetectRequest {
+    url: String,
+    headers: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Serialize)]
+struct DetectResponse {
+    url: String,
+    /// `graphql`, `rest` (a JSON response to GET), `other`, or `unknown`
+    /// when only the OPTIONS probe got an answer.
+    api_type: &'static str,
+    /// Methods advertised by `Allow` or `Access-Control-Allow-Methods` in
+    /// reply to OPTIONS.
+    methods: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    introspection_enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    duration_ms: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphQLLoadTestRequest {
+    #[serde(flatten)]
+    request: Option<GraphQLRequest>,
+    /// A traffic mix to use instead of a single request. Each iteration picks
+    /// one entry with probability proportional to its `weight`.
+    requests: Option<Vec<WeightedGraphQLRequest>>,
+    concurrency: Option<usize>,
+    total: Option<usize>,
+    /// Number of initial requests excluded from `latency` and reported
+    /// separately as `warmup_latency`.
+    warmup_count: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TlsBenchRequest {
+    host: String,
+    #[serde(default = "default_tls_port")]
+    port: u16,
+    /// Number of handshakes. Defaults to `LOADTEST_TOTAL`.
+    count: Option<usize>,
+    concurrency: Option<usize>,
+}
+
+fn default_tls_port() -> u16 {
+    443
+}
+
+#[derive(Debug, Deserialize)]
+struct WeightedGraphQLRequest {
+    request: GraphQLRequest,
+    weight: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ConvertRequest {
+    input: String,
+    from: String,
+    to: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SseRequest {
+    url: String,
+    headers: Option<HashMap<String, String>>,
+    duration: Option<u64>,
+    max_events: Option<usize>,
+    until_pattern: Option<String>,
+    /// Relays the events to the caller as a `text/event-stream` while they
+    /// arrive, ending with a `summary` event, instead of one JSON response.
+    forward: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 struct ProxyResponse {
     status: u16,
     headers: HashMap<String, String>,
+    /// The headers in the order received. Repeated names appear once per
+    /// value, grouped after the first occurrence of the name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ordered_headers: Option<Vec<[String; 2]>>,
     body: serde_json::Value,
+    /// A body that is not JSON, as text. `body` is then `null`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_text: Option<String>,
+    /// A binary body that is not JSON, base64-encoded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_base64: Option<String>,
+    /// Where to find the body: `json` in `body`, `text` in `body_text`,
+    /// `base64` in `body_base64` or `multipart` in `parts`. Absent for an
+    /// empty body or a range response.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_type: Option<String>,
     cached: bool,
     timestamp: String,
     duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_to_first_byte_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_compression: Option<RequestCompression>,
+    /// Length of the decoded `body_base64`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decoded_body_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    original_body_size: Option<usize>,
+    /// What a side-channel `probe_tls` handshake with the host negotiated,
+    /// not the request's own connection, whose parameters reqwest does not
+    /// expose. The probe uses the request's `min_tls_version` and ALPN for
+    /// `http_version`, but bypasses the DNS cache and cannot speak TLS 1.0 or
+    /// 1.1. Null when the probe fails or outlasts `TLS_PROBE_WAIT`.
+    tls_version: Option<String>,
+    cipher_suite: Option<String>,
+    /// The protocol the response arrived over, e.g. `HTTP/2.0`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    http_version: Option<String>,
+    /// Whether the server advertised HTTP/3 in `Alt-Svc`. Absent when it sent
+    /// no `Alt-Svc`; the upgrade itself is never taken.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    h3_advertised: Option<bool>,
+    header_bytes_sent: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sent_request: Option<SentRequest>,
+    /// The resolved configuration, for `debug_config`. Absent on a cache hit,
+    /// since nothing was sent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    config_trace: Option<ConfigTrace>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_body: Option<RawBody>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_kind: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    json_repaired: bool,
+    /// Set when this is a cached failure rather than a fresh response.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    negative_cached: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parts: Option<Vec<multipart::MultipartPart>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    final_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    download_throughput_bps: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range: Option<RangeResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace: Option<Vec<TraceSpan>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pagination: Option<Pagination>,
+    /// Whether the declared `Content-Length` disagrees with the bytes read.
+    /// Absent when the response declared no length.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_length_mismatch: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expect_continue: Option<rawhttp::ContinueOutcome>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    injected_delays: Option<InjectedDelays>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_regression: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_threshold_ms: Option<f64>,
+    /// The URL picked from `targets`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    /// The id sent for `check_request_id`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id_echoed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    assertion_results: Option<Vec<assertions::AssertionResult>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cookie_assertion_results: Option<Vec<assertions::CookieAssertionResult>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_type_ok: Option<bool>,
+    /// The response's `Content-Encoding`, reported for `expect_compressed`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_encoding: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compression_ok: Option<bool>,
+    /// The response's age, reported for `max_age_seconds`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    age_seconds: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    age_ok: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cacheable: Option<assertions::Cacheability>,
+    /// Whether every check requested on the response passed. Absent when the
+    /// request asked for none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    assertions_passed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decoded_jwt: Option<jwt::DecodedJwt>,
+    /// Set when the first attempt was dropped by an HTTP/2 GOAWAY and the
+    /// request was sent again on a new connection. Non-idempotent requests
+    /// are only resent when the server cannot have processed them.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    retried_on_goaway: bool,
+    /// Every challenge from the `WWW-Authenticate` headers, in order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    www_authenticate: Option<Vec<wwwauth::Challenge>>,
+    /// The upstream's own phase timings from its `Server-Timing` headers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server_timing: Option<Vec<ServerTimingMetric>>,
+    /// Every `Set-Cookie` header, in order; `headers` keeps only one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    set_cookies: Option<Vec<String>>,
+    /// Body bytes exactly as received, kept only for `raw_response`. Never
+    /// serialized, so entries read back from Redis do not have them.
+    #[serde(skip)]
+    upstream_body: Option<web::Bytes>,
+    /// Whether the DNS cache held the host when the request was sent.
+    #[serde(skip)]
+    dns_cache_hit: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct InjectedDelays {
+    pre_delay_ms: u64,
+    post_delay_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Pagination {
+    pages: usize,
+    page_urls: Vec<String>,
+    /// True when `max_pages` was reached while a next link remained.
+    truncated: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ServerTimingMetric {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct TraceSpan {
+    name: String,
+    start_ms: f64,
+    duration_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+/// Records the phases of a single proxied request. reqwest does not expose
+/// DNS, connect or TLS timings, so those are folded into `send`. When the
+/// DNS cache is enabled, `send` notes whether the host was cached.
+struct PhaseTrace {
+    origin: std::time::Instant,
+    last: std::time::Instant,
+    spans: Vec<TraceSpan>,
+}
+
+impl PhaseTrace {
+    fn new(origin: std::time::Instant) -> Self {
+        Self { origin, last: origin, spans: Vec::new() }
+    }
+
+    /// Closes the phase that started at the previous mark.
+    fn mark(&mut self, name: &str) {
+        let now = std::time::Instant::now();
+        self.record(name, self.last, now - self.last);
+        self.last = now;
+    }
+
+    fn record(&mut self, name: &str, start: std::time::Instant, duration: Duration) {
+        self.spans.push(TraceSpan {
+            name: name.to_string(),
+            start_ms: (start - self.origin).as_secs_f64() * 1000.0,
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            detail: None,
+        });
+    }
+
+    /// Annotates the most recently recorded span.
+    fn annotate(&mut self, detail: String) {
+        if let Some(span) = self.spans.last_mut() {
+            span.detail = Some(detail);
+        }
+    }
+
+    /// Continues from now after phases recorded out of band with `record`.
+    fn resume(&mut self) {
+        self.last = std::time::Instant::now();
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct RangeResult {
+    requested: String,
+    content_range: Option<String>,
+    bytes_received: usize,
+    /// How `body` is represented for a range response: `text` or `base64`.
+    body_encoding: String,
+}
+
+/// The response body exactly as it came off the wire, before any decoding.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct RawBody {
+    content_encoding: Option<String>,
+    body_base64: String,
+    compressed_size: usize,
+    uncompressed_size: Option<usize>,
+    /// Bytes decoded before hitting `MAX_DECOMPRESSED_BYTES`, when decoding
+    /// was aborted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    partial_uncompressed_size: Option<usize>,
+}
+
+/// The request exactly as handed to the HTTP client, with credentials masked.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct SentRequest {
+    method: String,
+    url: String,
+    headers: HashMap<String, String>,
+    body: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_base64: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_size: Option<usize>,
+}
+
+/// Where each setting a request ran with came from, once the environment
+/// defaults, injected headers, interceptors and the request itself have
+/// been layered.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ConfigTrace {
+    timeout_ms: u64,
+    /// `request` for `timeout_ms`, otherwise `config` for `REQUEST_TIMEOUT_SECS`.
+    timeout_source: String,
+    /// `none`, `cache` or, with `cache_failures`, `cache_with_failures`.
+    cache_strategy: String,
+    /// The scheme of the `Authorization` header sent, e.g. `Bearer`.
+    auth_type: Option<String>,
+    /// Every interceptor in effect, in the order they ran.
+    interceptors: Vec<interceptors::Interceptor>,
+    /// The headers sent, with sensitive values masked.
+    headers: BTreeMap<String, TracedHeader>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct TracedHeader {
+    value: String,
+    /// `interceptor`, `request`, `injected` or `proxy` for headers the proxy
+    /// adds itself, such as `Accept-Encoding` or the body's content type.
+    source: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct RequestCompression {
+    encoding: String,
+    original_size: usize,
+    compressed_size: usize,
 }
 
 #[derive(Debug, Serialize)]
@@ -91,7 +822,48 @@ struct WebSocketMessage {
 struct WebSocketResponse {
     messages: Vec<WebSocketMessage>,
     status: String,
-    duration: u64,
+    duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bytes_sent: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frame_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    steps: Option<Vec<WebSocketStepResult>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compression_negotiated: Option<bool>,
+    /// The `Sec-WebSocket-Extensions` value the server answered with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compression_extension: Option<String>,
+}
+
+/// The outcome of one `steps` entry. Steps after one that got no reply are
+/// not run, since they usually depend on it.
+#[derive(Debug, Serialize)]
+struct WebSocketStepResult {
+    sent: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply: Option<String>,
+    elapsed_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct WebSocketBenchResponse {
+    sent: usize,
+    received: usize,
+    /// Messages with no matching reply within the reply timeout.
+    lost: usize,
+    latency: Option<LatencyStats>,
+    duration_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct SseResponse {
+    status: u16,
+    events: Vec<sse::SseEvent>,
+    stop_reason: String,
+    duration_ms: u64,
 }
 
 #[derive(Debug, Serialize)]
@@ -101,247 +873,3620 @@ struct GraphQLResponse {
     duration_ms: u64,
 }
 
+#[derive(Debug, Serialize)]
+struct LatencyStats {
+    min_ms: f64,
+    mean_ms: f64,
+    p50_ms: f64,
+    p90_ms: f64,
+    p95_ms: f64,
+    p99_ms: f64,
+    max_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct GraphQLLoadTestResponse {
+    total: usize,
+    succeeded: usize,
+    transport_errors: usize,
+    graphql_errors: usize,
+    error_rate: f64,
+    latency: Option<LatencyStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warmup_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warmup_latency: Option<LatencyStats>,
+    duration_ms: u64,
+    requests_per_second: f64,
+    /// Per-entry results when a weighted `requests` mix was used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    by_request: Option<Vec<LoadTestMixStats>>,
+}
+
+#[derive(Debug, Serialize)]
+struct TlsBenchResponse {
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+    /// Failure messages and how often each occurred.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    errors: BTreeMap<String, usize>,
+    /// TCP connect plus TLS handshake.
+    latency: Option<LatencyStats>,
+    connect: Option<LatencyStats>,
+    handshake: Option<LatencyStats>,
+    duration_ms: u64,
+    handshakes_per_second: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct LoadTestMixStats {
+    index: usize,
+    weight: f64,
+    total: usize,
+    succeeded: usize,
+    transport_errors: usize,
+    graphql_errors: usize,
+    latency: Option<LatencyStats>,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenApiImportResponse {
+    base_url: String,
+    total: usize,
+    passed: usize,
+    failed: usize,
+    /// Operations not requested because a required parameter had no value.
+    skipped: usize,
+    operations: Vec<OperationCheck>,
+    duration_ms: u64,
+}
+
+/// The outcome of requesting one GET operation. It passes when the
+/// response status is below 400.
+#[derive(Debug, Serialize)]
+struct OperationCheck {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    operation_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_ms: Option<u64>,
+    passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    missing_parameters: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct MultiRegionResponse {
+    results: Vec<RegionResult>,
+    /// The region with the lowest `duration_ms` among those that responded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fastest: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slowest: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct RegionResult {
+    region: String,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchItemResult {
+    index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response: Option<ProxyResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchResponse {
+    results: Vec<BatchItemResult>,
+    succeeded: usize,
+    failed: usize,
+    duration_ms: u64,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    by_tag: HashMap<String, TagSummary>,
+    /// How many passing results `sample_rate` left out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sampled_out: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Default)]
+struct TagSummary {
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+    pass_rate: f64,
+}
+
 #[derive(Clone)]
 struct AppState {
-    cache: Cache<String, ProxyResponse>,
+    cache: cache::ResponseCache<ProxyResponse>,
+    negative_cache: cache::ResponseCache<NegativeEntry>,
     client: reqwest::Client,
+    ws_connections: Arc<Semaphore>,
+    injected_headers: Arc<RwLock<HashMap<String, String>>>,
+    cancellations: Arc<Mutex<HashMap<String, Arc<Notify>>>>,
+    interceptors: Arc<RwLock<Vec<interceptors::Interceptor>>>,
+    uploads: Arc<uploads::UploadStore>,
+    config: Arc<RwLock<config::RuntimeConfig>>,
+    dns_cache: Arc<dns::DnsCache>,
+    baselines: Arc<baselines::BaselineStore>,
+    chaos: Arc<chaos::Chaos>,
+    runs: Arc<runs::RunStore>,
+    jobs: Arc<jobs::JobStore<ProxyResponse>>,
+    hosts: Arc<hosts::HostStore>,
 }
 
-fn generate_cache_key(req: &ProxyRequest) -> String {
-    format!("{}:{}:{}:{}",
-        req.method,
-        req.url,
-        serde_json::to_string(&req.headers).unwrap_or_default(),
-        serde_json::to_string(&req.body).unwrap_or_default()
-    )
+impl AppState {
+    /// A snapshot of the runtime configuration. Handlers take one up front so
+    /// a reload mid-request does not mix old and new values.
+    fn config(&self) -> config::RuntimeConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Adds the host of `url` to the inventory behind `GET /hosts`.
+    fn record_host(&self, url: &str) {
+        if let Ok(url) = Url::parse(url) {
+            UPSTREAM_HOSTS_SEEN.set(self.hosts.record(&url) as i64);
+        }
+    }
 }
 
-async fn proxy(req: web::Json<ProxyRequest>, state: web::Data<AppState>) -> HttpResponse {
-    let start_time = std::time::Instant::now();
-    ACTIVE_REQUESTS.inc();
+/// Holds a WebSocket connection slot for the lifetime of a session. Dropping it
+/// releases the semaphore permit and updates the gauge, whichever way the
+/// handler exits.
+struct WsConnectionGuard {
+    _permit: OwnedSemaphorePermit,
+}
 
-    info!("Received {} request to {}", req.method, req.url);
+impl WsConnectionGuard {
+    fn acquire(semaphore: &Arc<Semaphore>) -> Option<Self> {
+        let permit = semaphore.clone().try_acquire_owned().ok()?;
+        ACTIVE_WS_CONNECTIONS.inc();
+        Some(Self { _permit: permit })
+    }
+}
 
-    if req.use_cache && req.method == "GET" {
-        let cache_key = generate_cache_key(&req);
-        if let Some(cached_response) = state.cache.get(&cache_key).await {
-            CACHE_HITS.inc();
-            info!("Cache hit for {}", req.url);
-            ACTIVE_REQUESTS.dec();
-            return HttpResponse::Ok().json(cached_response);
-        }
+impl Drop for WsConnectionGuard {
+    fn drop(&mut self) {
+        ACTIVE_WS_CONNECTIONS.dec();
     }
+}
 
-    let mut headers = HeaderMap::new();
-    if let Some(header_map) = &req.headers {
-        for (key, value) in header_map {
-            if let (Ok(header_name), Ok(header_value)) = (
-                HeaderName::from_str(key),
-                HeaderValue::from_str(value)
-            ) {
-                headers.insert(header_name, header_value);
-            }
+/// Inserts every valid header from `source`, replacing existing values with
+/// the same name. Invalid names or values are skipped.
+fn insert_headers(headers: &mut HeaderMap, source: &HashMap<String, String>) {
+    for (key, value) in source {
+        if let (Ok(header_name), Ok(header_value)) = (
+            HeaderName::from_str(key),
+            HeaderValue::from_str(value)
+        ) {
+            headers.insert(header_name, header_value);
         }
     }
+}
 
-    let request_builder: reqwest::RequestBuilder = match req.method.to_uppercase().as_str() {
-        "GET" => state.client.get(&req.url),
-        "POST" => state.client.post(&req.url),
-        "PUT" => state.client.put(&req.url),
-        "DELETE" => state.client.delete(&req.url),
-        "PATCH" => state.client.patch(&req.url),
-        _ => {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Unsupported HTTP method"
-            }))
-        }
-    };
+/// Approximate on-the-wire size of `headers`, counting the `: ` separator and
+/// trailing CRLF of each line.
+fn header_bytes(headers: &HeaderMap) -> usize {
+    headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len() + 4)
+        .sum()
+}
 
-    let request_builder = request_builder.headers(headers);
-    let request_builder = if let Some(body) = &req.body {
-        request_builder.json(body)
-    } else {
-        request_builder
-    };
-
-    match tokio::time::timeout(REQUEST_TIMEOUT, request_builder.send()).await {
-        Ok(result) => match result {
-            Ok(response) => {
-                let status = response.status().as_u16();
-                HTTP_REQUESTS_TOTAL.with_label_values(&[&req.method, &status.to_string()]).inc(); 
-                let headers: HashMap<String, String> = response
-                    .headers()
-                    .iter()
-                    .map(|(name, value)| (
-                        name.to_string(),
-                        value.to_str().unwrap_or("").to_string()
-                    ))
-                    .collect();
-
-                match response.json::<serde_json::Value>().await {
-                    Ok(body) => {
-                        let duration = start_time.elapsed();
-                        REQUEST_DURATION.observe(duration.as_secs_f64());
-                        let response_data = ProxyResponse {
-                            status,
-                            headers,
-                            body,
-                            cached: false,
-                            timestamp: Utc::now().to_rfc3339(),
-                            duration_ms: duration.as_millis() as u64,
-                        };
+fn check_header_limits(headers: &HeaderMap, config: &config::RuntimeConfig) -> Result<(), String> {
+    if headers.len() > config.max_outgoing_headers {
+        return Err(format!(
+            "Request has {} headers, exceeding the limit of {}",
+            headers.len(),
+            config.max_outgoing_headers
+        ));
+    }
+    if let Some((name, value)) = headers.iter().find(|(_, v)| v.len() > config.max_header_value_bytes) {
+        return Err(format!(
+            "Header '{}' is {} bytes, exceeding the limit of {}",
+            name,
+            value.len(),
+            config.max_header_value_bytes
+        ));
+    }
+    Ok(())
+}
 
-                        if req.use_cache && req.method == "GET" && status >= 200 && status < 300 {
-                            let cache_key = generate_cache_key(&req);
-                            state.cache.insert(cache_key, response_data.clone()).await;
-                        }
-                        ACTIVE_REQUESTS.dec();
-                        HttpResponse::Ok().json(response_data)
-                    }
-                    Err(e) => {
-                        error!("Failed to parse response body: {}", e);
-                        ACTIVE_REQUESTS.dec();
-                        HttpResponse::Ok().json(ProxyResponse {
-                            status,
-                            headers,
-                            body: serde_json::Value::Null,
-                            cached: false,
-                            timestamp: Utc::now().to_rfc3339(),
-                            duration_ms: start_time.elapsed().as_millis() as u64,
-                        })
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Request failed: {}", e);
-                ACTIVE_REQUESTS.dec();
-                HttpResponse::InternalServerError().json(serde_json::json!({
-                    "error": format!("Request failed: {}", e)
-                }))
-            }
-        },
-        Err(_) => {
-            error!("Request timeout");
-            ACTIVE_REQUESTS.dec();
-            HttpResponse::GatewayTimeout().json(serde_json::json!({
-                "error": "Request timeout"
-            }))
-        }
+const REDACTED: &str = "[REDACTED]";
+
+fn is_sensitive_header(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    matches!(
+        name.as_str(),
+        "authorization" | "proxy-authorization" | "cookie" | "set-cookie" | "x-api-key"
+    ) || name.contains("token")
+        || name.contains("secret")
+}
+
+/// Describes the built request. With `capture_bytes`, the body is also
+/// included byte for byte, after serialization and compression.
+fn describe_request(request: &reqwest::Request, capture_bytes: bool) -> SentRequest {
+    let body_bytes = request.body().and_then(|body| body.as_bytes());
+    SentRequest {
+        method: request.method().to_string(),
+        url: request.url().to_string(),
+        headers: request
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = if is_sensitive_header(name.as_str()) {
+                    REDACTED.to_string()
+                } else {
+                    value.to_str().unwrap_or("").to_string()
+                };
+                (name.to_string(), value)
+            })
+            .collect(),
+        body: body_bytes.map(|bytes| String::from_utf8_lossy(bytes).into_owned()),
+        body_base64: body_bytes.filter(|_| capture_bytes).map(|bytes| BASE64.encode(bytes)),
+        body_size: body_bytes.filter(|_| capture_bytes).map(|bytes| bytes.len()),
     }
 }
 
-async fn websocket(req: web::Json<WebSocketRequest>) -> HttpResponse {
-    let start_time = std::time::Instant::now();
-    
-    let url = match Url::parse(&req.url) {
-        Ok(url) => url,
-        Err(e) => {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": format!("Invalid WebSocket URL: {}", e)
-            }));
-        }
+/// Logs a `verbose` request's response before it is shaped, with sensitive
+/// headers masked.
+fn log_verbose_response(response: &ProxyResponse) {
+    let headers: HashMap<&str, &str> = response
+        .headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), if is_sensitive_header(name) { REDACTED } else { value.as_str() }))
+        .collect();
+    let body = match (&response.body_text, &response.body_base64) {
+        (Some(text), _) => text.clone(),
+        (None, Some(encoded)) => format!("base64:{}", encoded),
+        (None, None) => response.body.to_string(),
     };
+    info!(
+        target: logging::VERBOSE_TARGET,
+        "Response: {} {} {}",
+        response.status,
+        serde_json::to_string(&headers).unwrap_or_default(),
+        body
+    );
+}
 
-    let (ws_stream, _) = match connect_async(url).await {
-        Ok(conn) => conn,
-        Err(e) => {
+/// Builds the `config_trace` for a request about to be sent. A header's
+/// source is the last layer that set it: interceptors run after the request's
+/// own headers, which override injected ones.
+fn config_trace(
+    req: &ProxyRequest,
+    request: &reqwest::Request,
+    timeout: Duration,
+    interceptors: &[interceptors::Interceptor],
+    injected: &HashMap<String, String>,
+) -> ConfigTrace {
+    let source = |name: &str| {
+        let set_by_interceptor = interceptors.iter().any(|interceptor| {
+            matches!(interceptor, interceptors::Interceptor::SetHeader { name: set, .. } if set.eq_ignore_ascii_case(name))
+        });
+        let listed = |headers: &HashMap<String, String>| headers.keys().any(|key| key.eq_ignore_ascii_case(name));
+        if set_by_interceptor {
+            "interceptor"
+        } else if req.headers.as_ref().is_some_and(listed) {
+            "request"
+        } else if listed(injected) {
+            "injected"
+        } else {
+            "proxy"
+        }
+    };
+    let headers = request
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value = if is_sensitive_header(name.as_str()) {
+                REDACTED.to_string()
+            } else {
+                value.to_str().unwrap_or("").to_string()
+            };
+            (name.to_string(), TracedHeader { value, source: source(name.as_str()).to_string() })
+        })
+        .collect();
+    let cache_strategy = match (uses_cache(req), req.cache_failures.unwrap_or(false)) {
+        (false, _) => "none",
+        (true, false) => "cache",
+        (true, true) => "cache_with_failures",
+    };
+    ConfigTrace {
+        timeout_ms: timeout.as_millis() as u64,
+        timeout_source: if req.timeout_ms.is_some() { "request" } else { "config" }.to_string(),
+        cache_strategy: cache_strategy.to_string(),
+        auth_type: request
+            .headers()
+            .get(reqwest::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split_whitespace().next())
+            .map(str::to_string),
+        interceptors: interceptors.to_vec(),
+        headers,
+    }
+}
+
+/// A random id in UUID v4 format.
+fn generate_request_id() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex::encode(bytes);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+fn generate_cache_key(req: &ProxyRequest) -> String {
+    let key = format!("{}:{}:{}:{}",
+        req.method,
+        req.url,
+        serde_json::to_string(&req.headers).unwrap_or_default(),
+        serde_json::to_string(&req.body).unwrap_or_default()
+    );
+    let key = match req.body_type.as_deref() {
+        Some(body_type) if body_type != "json" => format!("{}:{}", key, body_type),
+        _ => key,
+    };
+    let key = match &req.body_base64 {
+        Some(encoded) => format!("{}:{}", key, encoded),
+        None => key,
+    };
+    // Both change what is stored: a decoded body differs from the one the
+    // shared client returns, and only a `raw_response` entry keeps the bytes.
+    format!("{}:{}:{}", key, decodes_body(req), req.raw_response.unwrap_or(false))
+}
+
+enum DecompressError {
+    Failed(String),
+    /// Output passed the size cap; decoding stopped after `partial_size` bytes.
+    TooLarge { partial_size: usize },
+}
+
+/// Decodes `data`, giving up once the output exceeds `max_size` bytes so a
+/// tiny compressed body cannot expand without bound.
+fn decompress_body(encoding: &str, data: &[u8], max_size: usize) -> Result<Vec<u8>, DecompressError> {
+    let decoder: Box<dyn Read + '_> = match encoding.trim().to_ascii_lowercase().as_str() {
+        "gzip" | "x-gzip" => Box::new(GzDecoder::new(data)),
+        "deflate" => Box::new(ZlibDecoder::new(data)),
+        "br" => Box::new(brotli::Decompressor::new(data, 4096)),
+        "identity" => Box::new(data),
+        other => return Err(DecompressError::Failed(format!("Unsupported content encoding: {}", other))),
+    };
+    let mut decompressed = Vec::new();
+    decoder
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| DecompressError::Failed(e.to_string()))?;
+    if decompressed.len() > max_size {
+        return Err(DecompressError::TooLarge { partial_size: decompressed.len() });
+    }
+    Ok(decompressed)
+}
+
+/// True when the request asks for compressed responses and decodes them
+/// itself, since the shared client does not.
+fn decodes_body(req: &ProxyRequest) -> bool {
+    req.raw_body.unwrap_or(false) || req.expect_compressed.is_some()
+}
+
+/// True when the request may be served from and stored in the response cache.
+/// Raw-body requests bypass it since cached entries no longer hold wire bytes,
+/// and range requests because the cache key does not include the range.
+fn uses_cache(req: &ProxyRequest) -> bool {
+    req.use_cache && req.method == "GET" && !req.raw_body.unwrap_or(false) && req.range.is_none()
+}
+
+/// True for `application/json`, `+json` types such as
+/// `application/problem+json`, and a missing content type, which is
+/// tried as JSON.
+fn is_json_media_type(content_type: &str) -> bool {
+    let media_type = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    media_type.is_empty() || media_type.ends_with("/json") || media_type.ends_with("+json")
+}
+
+/// Parses a JSON body. When strict parsing fails and `repair` is set, retries
+/// with JSON5, which accepts trailing commas, single quotes and unquoted keys.
+/// Returns the value and whether the repair pass was needed.
+fn parse_json_body(bytes: &[u8], repair: bool) -> Result<(serde_json::Value, bool), String> {
+    match serde_json::from_slice::<serde_json::Value>(bytes) {
+        Ok(value) => Ok((value, false)),
+        Err(e) if repair => std::str::from_utf8(bytes)
+            .ok()
+            .and_then(|text| json5::from_str::<serde_json::Value>(text).ok())
+            .map(|value| (value, true))
+            .ok_or_else(|| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Compares the declared `Content-Length` with the bytes actually read.
+/// hyper stops reading at the declared length, so an overlong body cannot be
+/// seen; a body cut short surfaces as a read error, which counts as a
+/// mismatch since fewer bytes arrived than were promised. A response to HEAD
+/// declares the length of a body it never sends, so it is not checked.
+fn content_length_mismatch(
+    head_request: bool,
+    status: u16,
+    headers: &HashMap<String, String>,
+    body_read: &Result<BodyRead, reqwest::Error>,
+) -> Option<bool> {
+    if head_request || status == 204 || status == 304 || (100..200).contains(&status) {
+        return None;
+    }
+    let declared: usize = headers.get(CONTENT_LENGTH.as_str())?.trim().parse().ok()?;
+    match body_read {
+        Ok(read) => Some(read.bytes.len() != declared),
+        Err(e) => e.is_body().then_some(true),
+    }
+}
+
+/// Splits on `separator` outside double-quoted strings.
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            c if c == separator && !in_quotes => {
+                parts.push(&value[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Parses `Server-Timing` values such as
+/// `db;dur=53.2, cache;desc="Cache Read";dur=2`. Unknown parameters are
+/// ignored, and so is a `dur` that is not a number.
+fn parse_server_timing<'a>(values: impl IntoIterator<Item = &'a str>) -> Vec<ServerTimingMetric> {
+    values
+        .into_iter()
+        .flat_map(|value| split_unquoted(value, ','))
+        .filter_map(|entry| {
+            let mut params = split_unquoted(entry, ';').into_iter();
+            let name = params.next()?.trim();
+            if name.is_empty() {
+                return None;
+            }
+            let mut metric = ServerTimingMetric { name: name.to_string(), duration_ms: None, description: None };
+            for param in params {
+                let Some((key, value)) = param.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim();
+                let value = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+                    Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+                    None => value.to_string(),
+                };
+                // Only the first occurrence of each parameter counts.
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "dur" if metric.duration_ms.is_none() => metric.duration_ms = value.parse().ok(),
+                    "desc" if metric.description.is_none() => metric.description = Some(value),
+                    _ => {}
+                }
+            }
+            Some(metric)
+        })
+        .collect()
+}
+
+struct BodyRead {
+    bytes: Vec<u8>,
+    time_to_first_byte: Option<Duration>,
+    /// Time spent reading the body, including any throttling delay.
+    transfer_time: Duration,
+}
+
+/// Reads the response body chunk by chunk, noting when the first chunk arrived
+/// relative to `start_time`. For streaming endpoints this is the latency that
+/// matters, independent of how long the stream runs.
+///
+/// With `max_bps`, each chunk is followed by a sleep until the running total
+/// is back under the cap. Chunks are as large as the socket delivers, so the
+/// rate is enforced on average rather than byte by byte.
+async fn read_body(
+    mut response: reqwest::Response,
+    start_time: std::time::Instant,
+    max_bps: Option<u64>,
+) -> Result<BodyRead, reqwest::Error> {
+    let read_start = tokio::time::Instant::now();
+    let mut bytes = Vec::new();
+    let mut time_to_first_byte = None;
+    while let Some(chunk) = response.chunk().await? {
+        if time_to_first_byte.is_none() {
+            time_to_first_byte = Some(start_time.elapsed());
+        }
+        bytes.extend_from_slice(&chunk);
+        if let Some(bps) = max_bps {
+            let allowed_at = read_start + Duration::from_secs_f64(bytes.len() as f64 / bps as f64);
+            tokio::time::sleep_until(allowed_at).await;
+        }
+    }
+    Ok(BodyRead { bytes, time_to_first_byte, transfer_time: read_start.elapsed() })
+}
+
+fn compress_body(encoding: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+    match encoding {
+        "gzip" => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data).map_err(|e| e.to_string())?;
+            encoder.finish().map_err(|e| e.to_string())
+        }
+        "br" => {
+            let mut compressed = Vec::new();
+            {
+                let mut encoder = brotli::CompressorWriter::new(&mut compressed, 4096, 5, 22);
+                encoder.write_all(data).map_err(|e| e.to_string())?;
+            }
+            Ok(compressed)
+        }
+        other => Err(format!("Unsupported request compression: {}", other)),
+    }
+}
+
+/// Encodes a `body` sent with a `body_type` other than `json`, returning the
+/// bytes and the content type to send when `headers` sets none:
+///
+/// - `form`: an object of scalars (or arrays of them), URL-encoded
+/// - `text`: a string, sent as-is
+/// - `base64`: a base64 string, decoded
+/// - `multipart`: an object encoded as `multipart/form-data`; a value such
+///   as `{"filename": "a.png", "content_base64": "..."}` is a file part
+fn encode_request_body(body_type: &str, body: &serde_json::Value) -> Result<(Vec<u8>, String), String> {
+    match body_type {
+        "form" => {
+            let fields = body.as_object().ok_or_else(|| "A form body must be an object".to_string())?;
+            let mut form = url::form_urlencoded::Serializer::new(String::new());
+            for (name, value) in fields {
+                let values = match value {
+                    serde_json::Value::Array(items) => items.iter().collect(),
+                    value => vec![value],
+                };
+                for value in values {
+                    let text = multipart::field_text(value).ok_or_else(|| {
+                        format!("Form field '{}' must be a string, number or boolean", name)
+                    })?;
+                    form.append_pair(name, &text);
+                }
+            }
+            Ok((form.finish().into_bytes(), "application/x-www-form-urlencoded".to_string()))
+        }
+        "text" => match body {
+            serde_json::Value::String(text) => Ok((text.clone().into_bytes(), "text/plain; charset=utf-8".to_string())),
+            _ => Err("A text body must be a string".to_string()),
+        },
+        "base64" => {
+            let encoded = body.as_str().ok_or_else(|| "A base64 body must be a string".to_string())?;
+            let bytes = BASE64.decode(encoded.trim()).map_err(|e| format!("Invalid base64 body: {}", e))?;
+            Ok((bytes, "application/octet-stream".to_string()))
+        }
+        "multipart" => {
+            let fields = body.as_object().ok_or_else(|| "A multipart body must be an object".to_string())?;
+            multipart::encode_form_data(fields)
+        }
+        other => Err(format!("Unsupported body_type: {}", other)),
+    }
+}
+
+/// Checks a `patch_type` request and returns the content type to send. A
+/// JSON Patch body must be an array of operations; a merge patch must be an
+/// object, since any other value would replace the target outright.
+fn patch_content_type(
+    patch_type: &str,
+    method: &str,
+    body: Option<&serde_json::Value>,
+) -> Result<&'static str, String> {
+    if !method.eq_ignore_ascii_case("PATCH") {
+        return Err("patch_type requires method PATCH".to_string());
+    }
+    let body = body.ok_or_else(|| "patch_type requires a body".to_string())?;
+    match patch_type {
+        "json-patch" => {
+            let ops = body
+                .as_array()
+                .ok_or_else(|| "A json-patch body must be an array of operations".to_string())?;
+            for (index, op) in ops.iter().enumerate() {
+                validate_json_patch_op(op).map_err(|e| format!("Invalid json-patch operation {}: {}", index, e))?;
+            }
+            Ok("application/json-patch+json")
+        }
+        "merge-patch" if body.is_object() => Ok("application/merge-patch+json"),
+        "merge-patch" => Err("A merge-patch body must be a JSON object".to_string()),
+        other => Err(format!("Unsupported patch_type: {}", other)),
+    }
+}
+
+fn validate_json_patch_op(op: &serde_json::Value) -> Result<(), String> {
+    let name = op
+        .get("op")
+        .and_then(serde_json::Value::as_str)
+        .ok_or_else(|| "missing string 'op'".to_string())?;
+    if !JSON_PATCH_OPS.contains(&name) {
+        return Err(format!("unknown op '{}'", name));
+    }
+    if !op.get("path").is_some_and(serde_json::Value::is_string) {
+        return Err("missing string 'path'".to_string());
+    }
+    if matches!(name, "move" | "copy") && !op.get("from").is_some_and(serde_json::Value::is_string) {
+        return Err(format!("'{}' needs a string 'from'", name));
+    }
+    if matches!(name, "add" | "replace" | "test") && op.get("value").is_none() {
+        return Err(format!("'{}' needs a 'value'", name));
+    }
+    Ok(())
+}
+
+/// Applies the per-request presentation options to a response, whether it
+/// came from the cache or straight from upstream.
+fn shape_response(req: &ProxyRequest, response: &mut ProxyResponse, config: &config::RuntimeConfig) {
+    // Evaluated here rather than stored, so a cache hit is checked against the
+    // assertions and `decode_jwt` of the request that hit it.
+    response.assertion_results = req
+        .assertions
+        .as_deref()
+        .map(|assertions| assertions::evaluate_all(assertions, &response.body));
+    response.cookie_assertion_results = req.cookie_assertions.as_ref().map(|checks| {
+        let set_cookies = response.set_cookies.as_deref().unwrap_or_default();
+        checks.iter().map(|check| check.evaluate(set_cookies, Utc::now())).collect()
+    });
+    let content_type_pattern = req.assert_content_type.as_deref().unwrap_or(&config.assert_content_type);
+    response.content_type_ok = (!content_type_pattern.is_empty()).then(|| {
+        assertions::content_type_matches(
+            content_type_pattern,
+            response.headers.get(CONTENT_TYPE.as_str()).map(String::as_str),
+        )
+    });
+    if let Some(expected) = req.expect_compressed {
+        response.content_encoding = response.headers.get(CONTENT_ENCODING.as_str()).cloned();
+        response.compression_ok = Some(is_compressed(response.content_encoding.as_deref()) == expected);
+    }
+    if let Some(max_age) = req.max_age_seconds {
+        response.age_seconds = assertions::response_age(
+            response.headers.get(AGE.as_str()).map(String::as_str),
+            response.headers.get(DATE.as_str()).map(String::as_str),
+            Utc::now(),
+        );
+        response.age_ok = Some(response.age_seconds.is_some_and(|age| age <= max_age));
+    }
+    if req.require_cacheable.unwrap_or(false) {
+        let header = |name: &HeaderName| response.headers.get(name.as_str()).map(String::as_str);
+        response.cacheable = Some(assertions::cacheability(
+            header(&CACHE_CONTROL),
+            header(&ETAG),
+            header(&LAST_MODIFIED),
+        ));
+    }
+    response.assertions_passed = assertions_passed(response);
+    response.decoded_jwt = req.decode_jwt.as_deref().map(|path| {
+        match jsonpath::query(&response.body, path).ok().flatten() {
+            Some(serde_json::Value::String(token)) => jwt::decode(&token, Utc::now()),
+            Some(_) => jwt::DecodedJwt {
+                error: Some(format!("Value at '{}' is not a string", path)),
+                ..Default::default()
+            },
+            None => jwt::DecodedJwt {
+                error: Some(format!("Path '{}' matched nothing", path)),
+                ..Default::default()
+            },
+        }
+    });
+    if req.sort_keys.unwrap_or(false) {
+        response.body = transforms::sort_keys(std::mem::take(&mut response.body));
+        if req.hash_body {
+            let canonical = serde_json::to_vec(&response.body).unwrap_or_default();
+            response.body_hash = Some(format!("{:x}", Sha256::digest(&canonical)));
+        }
+    }
+    if let Some(paths) = &req.select {
+        project_body(response, paths);
+    }
+    if let Some(pipeline) = req.transforms.as_deref().filter(|p| !p.is_empty()) {
+        let body = std::mem::take(&mut response.body);
+        if response.original_body_size.is_none() {
+            response.original_body_size = serde_json::to_vec(&body).ok().map(|b| b.len());
+        }
+        response.body = transforms::apply_all(pipeline, body);
+    }
+    if !req.hash_body {
+        response.body_hash = None;
+    }
+    if !req.ordered_headers.unwrap_or(false) {
+        response.ordered_headers = None;
+    }
+    response.tags = req.tags.clone();
+}
+
+/// Combines the outcome of every check run on the response.
+fn assertions_passed(response: &ProxyResponse) -> Option<bool> {
+    let outcomes: Vec<bool> = response
+        .assertion_results
+        .iter()
+        .flatten()
+        .map(|result| result.passed)
+        .chain(response.cookie_assertion_results.iter().flatten().map(|result| result.passed))
+        .chain(response.content_type_ok)
+        .chain(response.compression_ok)
+        .chain(response.age_ok)
+        .chain(response.cacheable.as_ref().map(|c| c.cacheable))
+        .collect();
+    (!outcomes.is_empty()).then(|| outcomes.iter().all(|passed| *passed))
+}
+
+/// True when a `Content-Encoding` names at least one real compression, as
+/// opposed to being absent or `identity`.
+fn is_compressed(content_encoding: Option<&str>) -> bool {
+    content_encoding.is_some_and(|value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .any(|encoding| !encoding.is_empty() && !encoding.eq_ignore_ascii_case("identity"))
+    })
+}
+
+/// Replaces the body with an object holding only the values found at `paths`,
+/// keyed by path. Paths that match nothing map to `null`.
+fn project_body(response: &mut ProxyResponse, paths: &[String]) {
+    let original = std::mem::take(&mut response.body);
+    response.original_body_size = serde_json::to_vec(&original).ok().map(|b| b.len());
+
+    let mut projected = serde_json::Map::new();
+    for path in paths {
+        let value = jsonpath::query(&original, path).ok().flatten();
+        projected.insert(path.clone(), value.unwrap_or(serde_json::Value::Null));
+    }
+    response.body = serde_json::Value::Object(projected);
+}
+
+/// Keeps the `active_requests` gauge accurate on every exit path of
+/// `execute_proxy`.
+struct ActiveRequestGuard;
+
+impl ActiveRequestGuard {
+    fn new() -> Self {
+        ACTIVE_REQUESTS.inc();
+        Self
+    }
+}
+
+impl Drop for ActiveRequestGuard {
+    fn drop(&mut self) {
+        ACTIVE_REQUESTS.dec();
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+enum ProxyError {
+    BadRequest(String),
+    RequestFailed(String),
+    /// The server could not meet the requested `min_tls_version`.
+    TlsPolicy(String),
+    Timeout,
+    Cancelled,
+    /// A failure replayed from the negative cache.
+    NegativeCached(Box<ProxyError>),
+}
+
+/// What the negative cache holds: an upstream 5xx or a request that never
+/// got a response.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+enum NegativeEntry {
+    Response(Box<ProxyResponse>),
+    Failure(ProxyError),
+}
+
+impl cache::Weigh for ProxyResponse {
+    fn unserialized_bytes(&self) -> usize {
+        self.upstream_body.as_ref().map_or(0, |body| body.len())
+    }
+}
+
+impl cache::Weigh for NegativeEntry {
+    fn unserialized_bytes(&self) -> usize {
+        match self {
+            NegativeEntry::Response(response) => response.unserialized_bytes(),
+            NegativeEntry::Failure(_) => 0,
+        }
+    }
+}
+
+impl ProxyError {
+    fn message(&self) -> String {
+        match self {
+            ProxyError::BadRequest(message) => message.clone(),
+            ProxyError::RequestFailed(e) => format!("Request failed: {}", e),
+            ProxyError::TlsPolicy(e) => format!("TLS policy not satisfied: {}", e),
+            ProxyError::Timeout => "Request timeout".to_string(),
+            ProxyError::Cancelled => "Request cancelled".to_string(),
+            ProxyError::NegativeCached(inner) => inner.message(),
+        }
+    }
+
+    fn kind(&self) -> Option<&'static str> {
+        match self {
+            ProxyError::TlsPolicy(_) => Some("tls_policy"),
+            ProxyError::Cancelled => Some("cancelled"),
+            ProxyError::NegativeCached(inner) => inner.kind(),
+            _ => None,
+        }
+    }
+
+    fn to_response(&self) -> HttpResponse {
+        let mut body = serde_json::json!({ "error": self.message() });
+        if let Some(kind) = self.kind() {
+            body["error_kind"] = serde_json::json!(kind);
+        }
+        if matches!(self, ProxyError::NegativeCached(_)) {
+            body["negative_cached"] = serde_json::json!(true);
+        }
+        match self {
+            ProxyError::BadRequest(_) => HttpResponse::BadRequest().json(body),
+            ProxyError::RequestFailed(_) => HttpResponse::InternalServerError().json(body),
+            ProxyError::TlsPolicy(_) => HttpResponse::BadGateway().json(body),
+            ProxyError::Timeout => HttpResponse::GatewayTimeout().json(body),
+            // 499 is the de facto "client closed request" status.
+            ProxyError::Cancelled => HttpResponse::build(StatusCode::from_u16(499).unwrap()).json(body),
+            ProxyError::NegativeCached(inner) => HttpResponse::build(inner.to_response().status()).json(body),
+        }
+    }
+}
+
+/// Joins an error with all of its sources; reqwest keeps TLS details deep in
+/// the chain. Sources already quoted by their parent are not repeated.
+fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(inner) = source {
+        let text = inner.to_string();
+        if !message.contains(&text) {
+            message.push_str(": ");
+            message.push_str(&text);
+        }
+        source = inner.source();
+    }
+    message
+}
+
+/// True when the request failed on an HTTP/2 GOAWAY and can safely be sent
+/// again. A GOAWAY with NO_ERROR, or a REFUSED_STREAM reset, only fails
+/// streams the server never processed, so any method is retried. A GOAWAY
+/// carrying an error code fails every open stream, including ones the server
+/// may already have processed, so only idempotent methods are retried then.
+fn is_retryable_goaway(e: &reqwest::Error, method: &reqwest::Method) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(e);
+    while let Some(inner) = source {
+        if let Some(h2) = inner.downcast_ref::<h2::Error>() {
+            if !h2.is_remote() {
+                return false;
+            }
+            let unprocessed = match h2.reason() {
+                Some(h2::Reason::NO_ERROR) => h2.is_go_away(),
+                Some(h2::Reason::REFUSED_STREAM) => h2.is_reset(),
+                _ => false,
+            };
+            return unprocessed || (h2.is_go_away() && method.is_idempotent());
+        }
+        source = inner.source();
+    }
+    false
+}
+
+/// Heuristic for handshake failures caused by protocol-version negotiation,
+/// as reported by OpenSSL, SChannel, Secure Transport and rustls.
+fn is_tls_version_failure(message: &str) -> bool {
+    const MARKERS: [&str; 6] = [
+        "protocol version",
+        "unsupported protocol",
+        "ProtocolVersion",
+        "HandshakeFailure",
+        "handshake failure",
+        "peer is incompatible",
+    ];
+    MARKERS.iter().any(|marker| message.contains(marker))
+}
+
+/// Collects the `probe_tls` handshake, giving it up to `TLS_PROBE_WAIT` past
+/// the response to finish. A probe still running after that is aborted.
+async fn await_tls_probe(probe: Option<tokio::task::JoinHandle<Option<tls::TlsDetails>>>) -> Option<tls::TlsDetails> {
+    let mut probe = probe?;
+    match tokio::time::timeout(TLS_PROBE_WAIT, &mut probe).await {
+        Ok(details) => details.ok().flatten(),
+        Err(_) => {
+            probe.abort();
+            None
+        }
+    }
+}
+
+/// Builds a client for this request only, for options the shared client
+/// cannot vary per request.
+fn scoped_client(
+    min_tls_version: Option<&str>,
+    http_version: Option<&str>,
+    dns_cache: &Arc<dns::DnsCache>,
+) -> Result<reqwest::Client, String> {
+    let builder = reqwest::Client::builder().dns_resolver(dns_cache.resolver());
+    let builder = match min_tls_version {
+        None => builder,
+        Some("1.0") => builder.min_tls_version(reqwest::tls::Version::TLS_1_0),
+        Some("1.1") => builder.min_tls_version(reqwest::tls::Version::TLS_1_1),
+        Some("1.2") => builder.min_tls_version(reqwest::tls::Version::TLS_1_2),
+        // The native TLS backend cannot enforce a TLS 1.3 floor; rustls can.
+        Some("1.3") => builder
+            .use_rustls_tls()
+            .min_tls_version(reqwest::tls::Version::TLS_1_3),
+        Some(other) => return Err(format!("Unsupported min_tls_version: {}", other)),
+    };
+    let builder = match http_version {
+        None => builder,
+        Some("1.1") => builder.http1_only(),
+        Some("2") => builder.http2_prior_knowledge(),
+        Some("3") => {
+            return Err(
+                "http_version 3 is not supported yet: this build has no QUIC client; \
+                 h3_advertised reports whether the server offers HTTP/3"
+                    .to_string(),
+            );
+        }
+        Some(other) => return Err(format!("Unsupported http_version: {}", other)),
+    };
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Removes a cancel token from the registry once its request finishes.
+struct CancelRegistration {
+    registry: Arc<Mutex<HashMap<String, Arc<Notify>>>>,
+    token: String,
+}
+
+impl Drop for CancelRegistration {
+    fn drop(&mut self) {
+        self.registry.lock().unwrap().remove(&self.token);
+    }
+}
+
+/// Picks a URL from `targets` by weight.
+fn pick_target(targets: &[WeightedTarget], seed: Option<u64>) -> Result<String, ProxyError> {
+    let weights = WeightedIndex::new(targets.iter().map(|target| target.weight))
+        .map_err(|e| ProxyError::BadRequest(format!("Invalid target weights: {}", e)))?;
+    let index = match seed {
+        Some(seed) => weights.sample(&mut StdRng::seed_from_u64(seed)),
+        None => weights.sample(&mut rand::thread_rng()),
+    };
+    Ok(targets[index].url.clone())
+}
+
+async fn execute_proxy(req: &ProxyRequest, state: &AppState) -> Result<ProxyResponse, ProxyError> {
+    let Some(run_id) = &req.run_id else {
+        return execute_targeted(req, state).await;
+    };
+    if !state.runs.contains(run_id) {
+        return Err(ProxyError::BadRequest(format!("Unknown run '{}'", run_id)));
+    }
+    let result = execute_targeted(req, state).await;
+    let outcome = result.as_ref().ok().map(|response| (response.status, response.duration_ms as f64));
+    state.runs.record(run_id, outcome);
+    let status = outcome.map_or_else(|| "error".to_string(), |(status, _)| status.to_string());
+    RUN_REQUESTS_TOTAL.with_label_values(&[run_id, &status]).inc();
+    if let Some((_, duration_ms)) = outcome {
+        RUN_REQUEST_DURATION.with_label_values(&[run_id]).observe(duration_ms / 1000.0);
+    }
+    result
+}
+
+async fn execute_targeted(req: &ProxyRequest, state: &AppState) -> Result<ProxyResponse, ProxyError> {
+    let Some(targets) = &req.targets else {
+        return execute_with_baseline(req, state).await;
+    };
+    if !req.url.is_empty() {
+        return Err(ProxyError::BadRequest("url cannot be combined with targets".to_string()));
+    }
+    let url = pick_target(targets, req.random_seed)?;
+    let request = ProxyRequest { url: url.clone(), targets: None, ..req.clone() };
+    let mut response = execute_with_baseline(&request, state).await?;
+    response.target = Some(url);
+    Ok(response)
+}
+
+async fn execute_with_baseline(req: &ProxyRequest, state: &AppState) -> Result<ProxyResponse, ProxyError> {
+    let Some(check) = &req.check_latency_baseline else {
+        return execute_cancellable(req, state).await;
+    };
+    let baseline = state.baselines.get_latency(&check.name).ok_or_else(|| {
+        ProxyError::BadRequest(format!("No latency baseline named '{}'", check.name))
+    })?;
+    let mut response = execute_cancellable(req, state).await?;
+    let threshold_ms = baseline.threshold_ms(check.stddevs, check.percent);
+    response.latency_regression = Some(response.duration_ms as f64 > threshold_ms);
+    response.latency_threshold_ms = Some(threshold_ms);
+    Ok(response)
+}
+
+/// Runs a proxied request, aborting it if its `cancel_token` is triggered
+/// through `POST /cancel/{token}` before it completes.
+async fn execute_cancellable(req: &ProxyRequest, state: &AppState) -> Result<ProxyResponse, ProxyError> {
+    let Some(token) = &req.cancel_token else {
+        return execute_delayed(req, state).await;
+    };
+
+    let notify = Arc::new(Notify::new());
+    {
+        let mut registry = state.cancellations.lock().unwrap();
+        if registry.contains_key(token) {
+            return Err(ProxyError::BadRequest(format!("Cancel token '{}' is already in use", token)));
+        }
+        registry.insert(token.clone(), notify.clone());
+    }
+    let _registration = CancelRegistration {
+        registry: state.cancellations.clone(),
+        token: token.clone(),
+    };
+
+    tokio::select! {
+        result = execute_delayed(req, state) => result,
+        _ = notify.notified() => {
+            info!("Request to {} cancelled via token {}", req.url, token);
+            Err(ProxyError::Cancelled)
+        }
+    }
+}
+
+/// Applies `pre_delay_ms` and `post_delay_ms` around the request. The delays
+/// sit outside the timed section, so `duration_ms` still reflects upstream,
+/// and inside cancellation, so a cancel token cuts a delay short.
+async fn execute_delayed(req: &ProxyRequest, state: &AppState) -> Result<ProxyResponse, ProxyError> {
+    if req.pre_delay_ms.is_none() && req.post_delay_ms.is_none() {
+        return execute_paginated(req, state).await;
+    }
+    let pre_delay_ms = req.pre_delay_ms.unwrap_or(0);
+    let post_delay_ms = req.post_delay_ms.unwrap_or(0);
+    if pre_delay_ms > MAX_INJECTED_DELAY_MS || post_delay_ms > MAX_INJECTED_DELAY_MS {
+        return Err(ProxyError::BadRequest(format!(
+            "Injected delays must not exceed {} ms",
+            MAX_INJECTED_DELAY_MS
+        )));
+    }
+
+    tokio::time::sleep(Duration::from_millis(pre_delay_ms)).await;
+    let mut response = execute_paginated(req, state).await?;
+    tokio::time::sleep(Duration::from_millis(post_delay_ms)).await;
+    response.injected_delays = Some(InjectedDelays { pre_delay_ms, post_delay_ms });
+    Ok(response)
+}
+
+/// Extracts the `rel="next"` target from an RFC 8288 `Link` header, resolved
+/// against the URL of the page it came from.
+fn next_link(link_header: &str, base: &str) -> Option<String> {
+    link_header.split('<').skip(1).find_map(|entry| {
+        let (target, params) = entry.split_once('>')?;
+        let is_next = params.split(';').any(|param| {
+            let Some((name, value)) = param.split_once('=') else {
+                return false;
+            };
+            name.trim().eq_ignore_ascii_case("rel")
+                && value
+                    .trim()
+                    .trim_end_matches(',')
+                    .trim_matches('"')
+                    .split_whitespace()
+                    .any(|rel| rel.eq_ignore_ascii_case("next"))
+        });
+        if !is_next {
+            return None;
+        }
+        Url::parse(base).and_then(|base| base.join(target.trim())).ok().map(String::from)
+    })
+}
+
+/// Runs the request and, with `follow_link_pagination`, keeps fetching the
+/// `rel="next"` page until there is none, a page fails or `max_pages` is hit.
+/// Array bodies are concatenated; any other bodies are collected into an
+/// array with one element per page. Pages are fetched unshaped, so `select`,
+/// `transforms` and assertions see the combined body.
+async fn execute_paginated(req: &ProxyRequest, state: &AppState) -> Result<ProxyResponse, ProxyError> {
+    let (mut response, trace) = fetch_unshaped(req, state).await?;
+    if req.follow_link_pagination.unwrap_or(false) {
+        follow_pages(req, state, &mut response).await?;
+    }
+    finish_response(req, &mut response, trace, &state.config());
+    Ok(response)
+}
+
+async fn follow_pages(req: &ProxyRequest, state: &AppState, response: &mut ProxyResponse) -> Result<(), ProxyError> {
+    let max_pages = req.max_pages.unwrap_or(DEFAULT_MAX_PAGES).clamp(1, MAX_PAGES_LIMIT);
+    let mut page_urls = vec![req.url.clone()];
+    let mut bodies = vec![std::mem::take(&mut response.body)];
+    let mut next = response.headers.get("link").and_then(|link| next_link(link, &req.url));
+    let mut truncated = false;
+
+    while let Some(url) = next.take() {
+        if page_urls.contains(&url) {
+            break;
+        }
+        if page_urls.len() >= max_pages {
+            truncated = true;
+            break;
+        }
+        let page_req = ProxyRequest { url: url.clone(), ..req.clone() };
+        let (mut page, _) = fetch_unshaped(&page_req, state).await?;
+        page_urls.push(url.clone());
+        bodies.push(std::mem::take(&mut page.body));
+        response.duration_ms += page.duration_ms;
+        if !(200..300).contains(&page.status) {
+            response.status = page.status;
+            break;
+        }
+        next = page.headers.get("link").and_then(|link| next_link(link, &url));
+    }
+
+    response.body = if bodies.iter().all(|b| b.is_array()) {
+        serde_json::Value::Array(
+            bodies
+                .into_iter()
+                .flat_map(|b| match b {
+                    serde_json::Value::Array(items) => items,
+                    _ => Vec::new(),
+                })
+                .collect(),
+        )
+    } else {
+        serde_json::Value::Array(bodies)
+    };
+    // The first page's hash no longer describes the body being returned.
+    if page_urls.len() > 1 && response.body_hash.is_some() {
+        let combined = serde_json::to_vec(&response.body).unwrap_or_default();
+        response.body_hash = Some(format!("{:x}", Sha256::digest(&combined)));
+    }
+    response.pagination = Some(Pagination { pages: page_urls.len(), page_urls, truncated });
+    Ok(())
+}
+
+/// Runs a single proxied request end to end: cache lookup, outbound send,
+/// body handling and cache population. The response is returned as received,
+/// before `shape_response`, together with the trace so far.
+async fn fetch_unshaped(req: &ProxyRequest, state: &AppState) -> Result<(ProxyResponse, PhaseTrace), ProxyError> {
+    let start_time = std::time::Instant::now();
+    let mut trace = PhaseTrace::new(start_time);
+    let config = state.config();
+
+    if let Some(paths) = &req.select {
+        if let Some(e) = paths.iter().find_map(|p| jsonpath::parse(p).err()) {
+            return Err(ProxyError::BadRequest(format!("Invalid select path: {}", e)));
+        }
+    }
+    if let Some(e) = req.transforms.iter().flatten().find_map(|t| t.validate().err()) {
+        return Err(ProxyError::BadRequest(e));
+    }
+    if let Some(e) = req.assertions.iter().flatten().find_map(|a| a.validate().err()) {
+        return Err(ProxyError::BadRequest(e));
+    }
+    if let Some(e) = req.cookie_assertions.iter().flatten().find_map(|a| a.validate().err()) {
+        return Err(ProxyError::BadRequest(e));
+    }
+    if let Some(Err(e)) = req.decode_jwt.as_deref().map(jsonpath::parse) {
+        return Err(ProxyError::BadRequest(format!("Invalid decode_jwt path: {}", e)));
+    }
+    if req.require_cacheable.unwrap_or(false) && !req.method.eq_ignore_ascii_case("GET") {
+        return Err(ProxyError::BadRequest("require_cacheable only applies to GET requests".to_string()));
+    }
+    if let Some(Err(e)) = req.success_criteria.as_ref().map(|criteria| jsonpath::parse(&criteria.path)) {
+        return Err(ProxyError::BadRequest(format!("Invalid success_criteria path: {}", e)));
+    }
+    let json_content_type = match &req.patch_type {
+        Some(patch_type) => patch_content_type(patch_type, &req.method, req.body.as_ref())
+            .map_err(ProxyError::BadRequest)?,
+        None => "application/json",
+    };
+    if let Some(ByteRange { start, end: Some(end) }) = &req.range {
+        if end < start {
+            return Err(ProxyError::BadRequest("range end must not be before start".to_string()));
+        }
+    }
+    if req.timeout_ms.is_some_and(|ms| ms == 0 || ms > MAX_REQUEST_TIMEOUT_MS) {
+        return Err(ProxyError::BadRequest(format!(
+            "timeout_ms must be between 1 and {}",
+            MAX_REQUEST_TIMEOUT_MS
+        )));
+    }
+    let request_timeout = req.timeout_ms.map(Duration::from_millis).unwrap_or_else(|| config.request_timeout());
+    if req.max_download_bps.is_some_and(|bps| bps < MIN_DOWNLOAD_BPS) {
+        return Err(ProxyError::BadRequest(format!(
+            "max_download_bps must be at least {}",
+            MIN_DOWNLOAD_BPS
+        )));
+    }
+    if req.raw_response.unwrap_or(false) && req.follow_link_pagination.unwrap_or(false) {
+        return Err(ProxyError::BadRequest(
+            "raw_response cannot be combined with follow_link_pagination".to_string(),
+        ));
+    }
+    if req.raw_response.unwrap_or(false) && req.to_csv.unwrap_or(false) {
+        return Err(ProxyError::BadRequest("raw_response cannot be combined with to_csv".to_string()));
+    }
+    if req.body_upload_id.is_some() && (req.body.is_some() || req.compress_request.is_some()) {
+        return Err(ProxyError::BadRequest(
+            "body_upload_id cannot be combined with body or compress_request".to_string(),
+        ));
+    }
+    if req.body_base64.is_some()
+        && (req.body.is_some() || req.body_upload_id.is_some() || req.compress_request.is_some())
+    {
+        return Err(ProxyError::BadRequest(
+            "body_base64 cannot be combined with body, body_upload_id or compress_request".to_string(),
+        ));
+    }
+    let body_type = req.body_type.as_deref().unwrap_or("json");
+    if !REQUEST_BODY_TYPES.contains(&body_type) {
+        return Err(ProxyError::BadRequest(format!("Unsupported body_type: {}", body_type)));
+    }
+    if body_type != "json" && (req.compress_request.is_some() || req.patch_type.is_some()) {
+        return Err(ProxyError::BadRequest(
+            "compress_request and patch_type require body_type json".to_string(),
+        ));
+    }
+    // Bodies sent as bytes rather than JSON, with the default content type.
+    let body_blob = match (&req.body_base64, &req.body) {
+        (Some(encoded), _) => Some((
+            BASE64
+                .decode(encoded.trim())
+                .map_err(|e| ProxyError::BadRequest(format!("Invalid body_base64: {}", e)))?,
+            "application/octet-stream".to_string(),
+        )),
+        (None, Some(body)) if body_type != "json" => {
+            Some(encode_request_body(body_type, body).map_err(ProxyError::BadRequest)?)
+        }
+        _ => None,
+    };
+    // The 100-continue exchange and unencoded paths go through the raw sender,
+    // which needs the body in memory rather than streamed from an upload.
+    let expect_continue = req.expect_continue.unwrap_or(false);
+    if expect_continue && req.body_upload_id.is_some() {
+        return Err(ProxyError::BadRequest(
+            "expect_continue cannot be combined with body_upload_id".to_string(),
+        ));
+    }
+    if req.encode_path == Some(false) && req.body_upload_id.is_some() {
+        return Err(ProxyError::BadRequest(
+            "encode_path: false cannot be combined with body_upload_id".to_string(),
+        ));
+    }
+    if req.http_version.as_deref() == Some("2") && (expect_continue || req.encode_path == Some(false)) {
+        return Err(ProxyError::BadRequest(
+            "http_version 2 cannot be combined with expect_continue or encode_path: false".to_string(),
+        ));
+    }
+    trace.mark("validation");
+
+    let _active = ActiveRequestGuard::new();
+
+    info!("Received {} request to {}", req.method, req.url);
+
+    // Namespaced so a failure never shares a Redis key with a good response.
+    let negative_key = (uses_cache(req) && req.cache_failures.unwrap_or(false))
+        .then(|| format!("negative:{}", generate_cache_key(req)));
+    if uses_cache(req) {
+        let cache_key = generate_cache_key(req);
+        // An entry stored for an enveloped request has no raw bytes to return.
+        let cached = state
+            .cache
+            .get(&cache_key)
+            .await
+            .filter(|cached| !req.raw_response.unwrap_or(false) || cached.upstream_body.is_some());
+        if let Some(cached_response) = cached {
+            CACHE_HITS.inc();
+            info!("Cache hit for {}", req.url);
+            trace.mark("cache_lookup");
+            return Ok((cached_response, trace));
+        }
+        if let Some(key) = &negative_key {
+            match state.negative_cache.get(key).await {
+                Some(NegativeEntry::Response(cached))
+                    if !req.raw_response.unwrap_or(false) || cached.upstream_body.is_some() =>
+                {
+                    let mut cached_response = *cached;
+                    info!("Negative cache hit for {}", req.url);
+                    cached_response.negative_cached = true;
+                    trace.mark("cache_lookup");
+                    return Ok((cached_response, trace));
+                }
+                Some(NegativeEntry::Failure(e)) => {
+                    info!("Negative cache hit for {}", req.url);
+                    return Err(ProxyError::NegativeCached(Box::new(e)));
+                }
+                _ => {}
+            }
+        }
+        trace.mark("cache_lookup");
+    }
+
+    let mut headers = HeaderMap::new();
+    insert_headers(&mut headers, &state.injected_headers.read().unwrap());
+    if let Some(header_map) = &req.headers {
+        insert_headers(&mut headers, header_map);
+    }
+    if let Some(range) = &req.range {
+        headers.insert(RANGE, HeaderValue::from_str(&range.header_value()).expect("range header is ASCII"));
+    }
+    let mut url = req.url.clone();
+    let interceptors = state.interceptors.read().unwrap().clone();
+    interceptors::apply_request(&interceptors, &mut headers, &mut url);
+    // An id the caller already set is kept, so a known value can be checked.
+    let request_id = match &req.check_request_id {
+        Some(check) => {
+            let name = HeaderName::from_str(&check.header)
+                .map_err(|_| ProxyError::BadRequest(format!("Invalid request id header: {}", check.header)))?;
+            let id = match headers.get(&name).and_then(|value| value.to_str().ok()) {
+                Some(existing) => existing.to_string(),
+                None => {
+                    let id = generate_request_id();
+                    headers.insert(name, HeaderValue::from_str(&id).expect("request id is ASCII"));
+                    id
+                }
+            };
+            Some((check.echo_header.clone().unwrap_or_else(|| check.header.clone()), id))
+        }
+        None => None,
+    };
+    // The shared client is built without decompression support, so the bytes
+    // we read are exactly what the server sent. Advertise compression so the
+    // server has the chance to use it.
+    if decodes_body(req) && !headers.contains_key(ACCEPT_ENCODING) {
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip, deflate, br"));
+    }
+
+    if let Err(e) = check_header_limits(&headers, &config) {
+        if req.allow_oversized_headers {
+            warn!("Sending oversized headers to {}: {}", req.url, e);
+        } else {
+            return Err(ProxyError::BadRequest(format!(
+                "{}. Set allow_oversized_headers to send anyway",
+                e
+            )));
+        }
+    }
+    let header_bytes_sent = header_bytes(&headers);
+    trace.mark("header_build");
+
+    let client = if req.min_tls_version.is_some() || req.http_version.is_some() {
+        scoped_client(req.min_tls_version.as_deref(), req.http_version.as_deref(), &state.dns_cache)
+            .map_err(ProxyError::BadRequest)?
+    } else {
+        state.client.clone()
+    };
+
+    let method = req.method.to_uppercase();
+    if !SUPPORTED_METHODS.contains(&method.as_str()) {
+        return Err(ProxyError::BadRequest("Unsupported HTTP method".to_string()));
+    }
+    let method = reqwest::Method::from_str(&method).expect("supported methods are valid");
+    let request_builder = client.request(method, &url).timeout(request_timeout);
+
+    let mut request_compression = None;
+    let has_content_type = headers.contains_key(CONTENT_TYPE);
+    let request_builder = request_builder.headers(headers);
+    let json_body = req.body.as_ref().filter(|_| body_type == "json");
+    let request_builder = match (json_body, &req.compress_request) {
+        (Some(body), Some(encoding)) => {
+            let original = serde_json::to_vec(body).unwrap_or_default();
+            let compressed = compress_body(encoding, &original).map_err(ProxyError::BadRequest)?;
+            request_compression = Some(RequestCompression {
+                encoding: encoding.clone(),
+                original_size: original.len(),
+                compressed_size: compressed.len(),
+            });
+            let request_builder = request_builder.header(CONTENT_ENCODING, encoding.as_str());
+            let request_builder = if has_content_type {
+                request_builder
+            } else {
+                request_builder.header(CONTENT_TYPE, json_content_type)
+            };
+            request_builder.body(compressed)
+        }
+        (Some(body), None) if has_content_type => request_builder.json(body),
+        (Some(body), None) => request_builder.header(CONTENT_TYPE, json_content_type).json(body),
+        (None, _) => request_builder,
+    };
+    // Held until the response has been read; dropping it deletes the file.
+    let mut _upload_guard = None;
+    let request_builder = match &req.body_upload_id {
+        Some(id) => {
+            let (upload, guard) = state
+                .uploads
+                .take(id)
+                .ok_or_else(|| ProxyError::BadRequest(format!("Unknown or expired upload '{}'", id)))?;
+            let file = tokio::fs::File::open(&upload.path)
+                .await
+                .map_err(|e| ProxyError::RequestFailed(format!("Cannot read upload '{}': {}", id, e)))?;
+            _upload_guard = Some(guard);
+            let request_builder = match (&upload.content_type, has_content_type) {
+                (Some(content_type), false) => request_builder.header(CONTENT_TYPE, content_type.as_str()),
+                _ => request_builder,
+            };
+            request_builder
+                .header(CONTENT_LENGTH, upload.size)
+                .body(reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(file)))
+        }
+        None => request_builder,
+    };
+    let decoded_body_size = req.body_base64.as_ref().and(body_blob.as_ref()).map(|(bytes, _)| bytes.len());
+    let request_builder = match body_blob {
+        Some((bytes, _)) if has_content_type => request_builder.body(bytes),
+        Some((bytes, content_type)) => request_builder.header(CONTENT_TYPE, content_type).body(bytes),
+        None => request_builder,
+    };
+
+    let request = request_builder
+        .build()
+        .map_err(|e| ProxyError::BadRequest(format!("Invalid request: {}", e)))?;
+    trace.mark("request_build");
+    let raw_target = if req.encode_path == Some(false) {
+        Some(rawhttp::request_target(&url))
+    } else if expect_continue {
+        let url = request.url();
+        Some(match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        })
+    } else {
+        None
+    };
+    let final_url = match &raw_target {
+        Some(target) => format!("{}{}", request.url().origin().ascii_serialization(), target),
+        None => request.url().to_string(),
+    };
+    let capture = req.capture_request.unwrap_or(false);
+    // Checked before sending; an idle pooled connection may skip the lookup.
+    let dns_cache_hit = (raw_target.is_none() && state.dns_cache.enabled())
+        .then(|| request.url().host_str().is_some_and(|host| state.dns_cache.contains(host)));
+    let sent_request = (capture || req.echo_request.unwrap_or(false)).then(|| SentRequest {
+        url: final_url.clone(),
+        ..describe_request(&request, capture)
+    });
+    let config_trace = req.debug_config.unwrap_or(false).then(|| {
+        let injected = state.injected_headers.read().unwrap();
+        config_trace(req, &request, request_timeout, &interceptors, &injected)
+    });
+    if req.verbose.unwrap_or(false) {
+        let sent = SentRequest { url: final_url.clone(), ..describe_request(&request, false) };
+        info!(target: logging::VERBOSE_TARGET, "Request: {}", serde_json::to_string(&sent).unwrap_or_default());
+    }
+
+    state.record_host(request.url().as_str());
+    let tls_probe = (req.probe_tls.unwrap_or(false) && request.url().scheme() == "https").then(|| {
+        let url = request.url().to_string();
+        let (min_tls_version, http_version) = (req.min_tls_version.clone(), req.http_version.clone());
+        tokio::spawn(async move { tls::probe_url(&url, min_tls_version.as_deref(), http_version.as_deref()).await })
+    });
+    let mut retried_on_goaway = false;
+    let send = async {
+        let send_start = std::time::Instant::now();
+        let result = match &raw_target {
+            Some(target) => rawhttp::send(request, target, expect_continue).await.map_err(|e| {
+                error!("Request failed: {}", e);
+                ProxyError::RequestFailed(e)
+            }),
+            None => {
+                // reqwest itself retries the unprocessed cases a couple of
+                // times; this adds one more attempt and covers idempotent
+                // requests caught by an erroring GOAWAY. A streamed upload body
+                // cannot be cloned, so it is not retried.
+                let method = request.method().clone();
+                let retry = request.try_clone();
+                let mut result = client.execute(request).await;
+                let retryable = result.as_ref().err().is_some_and(|e| is_retryable_goaway(e, &method));
+                if let Some(retry) = retry.filter(|_| retryable) {
+                    warn!("Upstream sent GOAWAY; retrying on a new connection");
+                    retried_on_goaway = true;
+                    result = client.execute(retry).await;
+                }
+                result.map(|response| (response, None)).map_err(|e| {
+                    error!("Request failed: {}", e);
+                    // The client's own deadline, set to the same timeout,
+                    // usually fires before the outer one.
+                    if e.is_timeout() {
+                        return ProxyError::Timeout;
+                    }
+                    let chain = error_chain(&e);
+                    if req.min_tls_version.is_some() && e.is_connect() && is_tls_version_failure(&chain) {
+                        return ProxyError::TlsPolicy(chain);
+                    }
+                    ProxyError::RequestFailed(e.to_string())
+                })
+            }
+        };
+        (result, send_start, send_start.elapsed())
+    };
+    let send_result = tokio::time::timeout(request_timeout, send).await;
+    let send_result = send_result.map(|(result, send_start, send_duration)| {
+        trace.record("send", send_start, send_duration);
+        if let Some(hit) = dns_cache_hit {
+            trace.annotate(format!("dns_cache_{}", if hit { "hit" } else { "miss" }));
+        }
+        result
+    });
+    trace.resume();
+
+    let send_result = match send_result {
+        Ok(result) => result,
+        Err(_) => {
+            error!("Request timeout");
+            Err(ProxyError::Timeout)
+        }
+    };
+    let (response, continue_outcome) = match send_result {
+        Ok(sent) => sent,
+        Err(e) => {
+            if let (Some(key), ProxyError::RequestFailed(_) | ProxyError::Timeout) = (&negative_key, &e) {
+                state.negative_cache.insert(key.clone(), NegativeEntry::Failure(e.clone())).await;
+            }
+            return Err(e);
+        }
+    };
+
+    let status = response.status().as_u16();
+    let http_version = Some(format!("{:?}", response.version()));
+    let h3_advertised = response
+        .headers()
+        .get(reqwest::header::ALT_SVC)
+        .map(|alt_svc| alt_svc.to_str().unwrap_or("").split(',').any(|entry| entry.trim().starts_with("h3")));
+    // reqwest hands back a redirect it could not follow as an ordinary
+    // response; flag it so a malformed redirect is not mistaken for a result.
+    let error_kind = (REDIRECT_STATUSES.contains(&status) && !response.headers().contains_key(LOCATION))
+        .then(|| "redirect_no_location".to_string());
+    HTTP_REQUESTS_TOTAL.with_label_values(&[&req.method.to_uppercase(), &status.to_string()]).inc();
+    // Read from the header map directly: a server may send several
+    // `WWW-Authenticate` headers and the flattened map keeps only one.
+    let www_authenticate = Some(wwwauth::parse_all(
+        response
+            .headers()
+            .get_all(reqwest::header::WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|value| value.to_str().ok()),
+    ))
+    .filter(|challenges| !challenges.is_empty());
+    let server_timing = Some(parse_server_timing(
+        response
+            .headers()
+            .get_all("server-timing")
+            .iter()
+            .filter_map(|value| value.to_str().ok()),
+    ))
+    .filter(|timings| !timings.is_empty());
+    let set_cookies = Some(
+        response
+            .headers()
+            .get_all(reqwest::header::SET_COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok().map(str::to_string))
+            .collect::<Vec<_>>(),
+    )
+    .filter(|cookies| !cookies.is_empty());
+    let headers: HashMap<String, String> = response
+        .headers()
+        .iter()
+        .map(|(name, value)| (
+            name.to_string(),
+            value.to_str().unwrap_or("").to_string()
+        ))
+        .collect();
+    let ordered_headers: Vec<[String; 2]> = response
+        .headers()
+        .iter()
+        .map(|(name, value)| [name.to_string(), value.to_str().unwrap_or("").to_string()])
+        .collect();
+
+    // The deadline covers the whole exchange, so it can also expire while the
+    // body is still arriving or a `max_download_bps` read is throttled.
+    let remaining = request_timeout.saturating_sub(start_time.elapsed());
+    let read = tokio::time::timeout(remaining, read_body(response, start_time, req.max_download_bps)).await;
+    let mut body_read = match read {
+        Ok(Err(e)) if !e.is_timeout() => Err(e),
+        Ok(Ok(read)) => Ok(read),
+        Ok(Err(_)) | Err(_) => {
+            error!("Request timed out while reading the body");
+            if let Some(key) = &negative_key {
+                state.negative_cache.insert(key.clone(), NegativeEntry::Failure(ProxyError::Timeout)).await;
+            }
+            return Err(ProxyError::Timeout);
+        }
+    };
+    trace.mark("body_read");
+    let time_to_first_byte = body_read.as_ref().ok().and_then(|read| read.time_to_first_byte);
+    let download_throughput_bps = req
+        .max_download_bps
+        .and(body_read.as_ref().ok())
+        .map(|read| read.bytes.len() as f64 / read.transfer_time.as_secs_f64().max(f64::EPSILON));
+    // Hashed before parsing so the digest reflects the exact bytes received.
+    let body_hash = body_read
+        .as_ref()
+        .ok()
+        .map(|read| format!("{:x}", Sha256::digest(&read.bytes)));
+    let head_request = req.method.eq_ignore_ascii_case("HEAD");
+    let content_length_mismatch = content_length_mismatch(head_request, status, &headers, &body_read);
+    let request_id_echoed = request_id
+        .as_ref()
+        .map(|(echo_header, id)| headers.get(&echo_header.to_ascii_lowercase()) == Some(id));
+    let upstream_body = req
+        .raw_response
+        .unwrap_or(false)
+        .then(|| body_read.as_ref().ok().map(|read| web::Bytes::copy_from_slice(&read.bytes)))
+        .flatten();
+
+    let mut raw_body = None;
+    let mut decompression_bomb = false;
+    if decodes_body(req) {
+        if let Ok(read) = body_read.as_mut() {
+            let content_encoding = headers.get(CONTENT_ENCODING.as_str()).cloned();
+            let decoded = content_encoding
+                .as_deref()
+                .map(|encoding| decompress_body(encoding, &read.bytes, config.max_decompressed_bytes));
+            let partial_uncompressed_size = match &decoded {
+                Some(Err(DecompressError::TooLarge { partial_size })) => {
+                    warn!(
+                        "Aborted decompressing {} bytes from {} after {} bytes",
+                        read.bytes.len(),
+                        req.url,
+                        partial_size
+                    );
+                    decompression_bomb = true;
+                    Some(*partial_size)
+                }
+                Some(Err(DecompressError::Failed(e))) => {
+                    warn!("Could not decode response body from {}: {}", req.url, e);
+                    None
+                }
+                _ => None,
+            };
+            let decoded = decoded.and_then(Result::ok);
+            if req.raw_body.unwrap_or(false) {
+                raw_body = Some(RawBody {
+                    content_encoding,
+                    body_base64: BASE64.encode(&read.bytes),
+                    compressed_size: read.bytes.len(),
+                    uncompressed_size: decoded.as_ref().map(|d| d.len()),
+                    partial_uncompressed_size,
+                });
+            }
+            match decoded {
+                Some(decoded) => read.bytes = decoded,
+                // Leave nothing for the parser rather than the compressed bytes.
+                None if decompression_bomb => read.bytes.clear(),
+                None => {}
+            }
+        }
+    }
+    let content_type = headers.get(CONTENT_TYPE.as_str()).cloned().unwrap_or_default();
+    let mut parts = None;
+    let mut multipart_failed = false;
+    if multipart::is_multipart(&content_type) {
+        if let Ok(read) = &body_read {
+            match multipart::parse(&content_type, &read.bytes).await {
+                Ok(parsed_parts) => parts = Some(parsed_parts),
+                Err(e) => {
+                    warn!("Failed to parse multipart response from {}: {}", req.url, e);
+                    multipart_failed = true;
+                }
+            }
+        }
+    }
+
+    // Partial content is rarely valid JSON on its own, so range responses are
+    // returned as text or base64 rather than parsed.
+    let mut range = None;
+    if let (Some(requested), Ok(read)) = (&req.range, &body_read) {
+        let (range_body, body_encoding) = multipart::decode_body(None, &read.bytes);
+        range = Some((
+            RangeResult {
+                requested: requested.header_value(),
+                content_range: headers.get(CONTENT_RANGE.as_str()).cloned(),
+                bytes_received: read.bytes.len(),
+                body_encoding: body_encoding.to_string(),
+            },
+            range_body,
+        ));
+    }
+
+    let mut json_repaired = false;
+    let mut unparsed_body = None;
+    let parsed = if parts.is_some() || head_request {
+        Ok(serde_json::Value::Null)
+    } else if let Some((_, range_body)) = &range {
+        Ok(range_body.clone())
+    } else {
+        match body_read {
+            // Only JSON media types are parsed, so a text body that happens
+            // to be valid JSON, such as `123`, stays text.
+            Ok(read) if !is_json_media_type(&content_type) && !read.bytes.is_empty() => {
+                unparsed_body = Some(read.bytes);
+                Err(format!("Not a JSON content type: {}", content_type))
+            }
+            Ok(read) => match parse_json_body(&read.bytes, req.repair_json.unwrap_or(false)) {
+                Ok((value, repaired)) => {
+                    json_repaired = repaired;
+                    Ok(value)
+                }
+                Err(e) => {
+                    if !read.bytes.is_empty() {
+                        unparsed_body = Some(read.bytes);
+                    }
+                    Err(e)
+                }
+            },
+            Err(e) => Err(e.to_string()),
+        }
+    };
+    // Bodies that are not JSON are passed on as text or base64 instead of
+    // being dropped.
+    let (body_text, body_base64, body_type) = match unparsed_body {
+        Some(bytes) => match multipart::decode_body(Some(&content_type), &bytes) {
+            (serde_json::Value::String(text), "text") => (Some(text), None, Some("text")),
+            _ => (None, Some(BASE64.encode(&bytes)), Some("base64")),
+        },
+        None if parts.is_some() => (None, None, Some("multipart")),
+        None if range.is_none() && !head_request && parsed.is_ok() => (None, None, Some("json")),
+        None => (None, None, None),
+    };
+
+    let (body, parsed_ok) = match parsed {
+        Ok(mut body) => {
+            interceptors::apply_response(&interceptors, &mut body);
+            (body, true)
+        }
+        Err(e) => {
+            if upstream_body.is_none() && body_type.is_none() {
+                error!("Failed to parse response body: {}", e);
+            }
+            (serde_json::Value::Null, false)
+        }
+    };
+    // A raw response or a text or binary body does not need to be JSON to
+    // count as a usable result, so those are cached too.
+    let body_ok = parsed_ok || upstream_body.is_some() || body_text.is_some() || body_base64.is_some();
+
+    let duration = start_time.elapsed();
+    if body_ok {
+        REQUEST_DURATION.observe(duration.as_secs_f64());
+    }
+    if let Some(criteria) = &req.success_criteria {
+        let matched = jsonpath::query(&body, &criteria.path).ok().flatten().as_ref() == Some(&criteria.equals);
+        let outcome = if parsed_ok && matched { "success" } else { "failure" };
+        LOGICAL_REQUESTS_TOTAL.with_label_values(&[outcome]).inc();
+    }
+    // Waited for after `duration` is taken, and left out of the trace.
+    let tls_details = await_tls_probe(tls_probe).await;
+    trace.resume();
+    let (tls_version, cipher_suite) = match tls_details {
+        Some(details) => (Some(details.tls_version), Some(details.cipher_suite)),
+        None => (None, None),
+    };
+    let mut response_data = ProxyResponse {
+        status,
+        headers,
+        ordered_headers: Some(ordered_headers),
+        body,
+        body_text,
+        body_base64,
+        body_type: body_type.map(str::to_string),
+        cached: false,
+        timestamp: Utc::now().to_rfc3339(),
+        duration_ms: duration.as_millis() as u64,
+        time_to_first_byte_ms: time_to_first_byte.map(|d| d.as_millis() as u64),
+        request_compression,
+        decoded_body_size,
+        original_body_size: None,
+        tls_version,
+        cipher_suite,
+        http_version,
+        h3_advertised,
+        header_bytes_sent,
+        body_hash,
+        sent_request: None,
+        config_trace: None,
+        raw_body,
+        error_kind: error_kind
+            .or_else(|| decompression_bomb.then(|| "decompression_bomb".to_string()))
+            .or_else(|| multipart_failed.then(|| "invalid_multipart".to_string()))
+            .or_else(|| (req.range.is_some() && status != 206).then(|| "range_not_honored".to_string())),
+        json_repaired,
+        negative_cached: false,
+        parts,
+        tags: None,
+        final_url: Some(final_url),
+        download_throughput_bps,
+        range: range.map(|(result, _)| result),
+        trace: None,
+        pagination: None,
+        content_length_mismatch,
+        expect_continue: continue_outcome,
+        injected_delays: None,
+        latency_regression: None,
+        latency_threshold_ms: None,
+        target: None,
+        request_id_echoed,
+        request_id: request_id.map(|(_, id)| id),
+        assertion_results: None,
+        cookie_assertion_results: None,
+        content_type_ok: None,
+        content_encoding: None,
+        compression_ok: None,
+        age_seconds: None,
+        age_ok: None,
+        cacheable: None,
+        assertions_passed: None,
+        decoded_jwt: None,
+        retried_on_goaway,
+        www_authenticate,
+        server_timing,
+        set_cookies,
+        upstream_body,
+        dns_cache_hit,
+    };
+
+    let cacheable = match &req.cacheable_statuses {
+        Some(statuses) => statuses.contains(&status),
+        None => (200..300).contains(&status),
+    };
+    if body_ok && uses_cache(req) && cacheable {
+        let cache_key = generate_cache_key(req);
+        state.cache.insert(cache_key, response_data.clone()).await;
+    } else if let Some(key) = negative_key.filter(|_| status >= 500) {
+        state.negative_cache.insert(key, NegativeEntry::Response(Box::new(response_data.clone()))).await;
+    }
+    // Attached after caching: a later cache hit sends nothing upstream.
+    response_data.sent_request = sent_request;
+    response_data.config_trace = config_trace;
+    if req.verbose.unwrap_or(false) {
+        log_verbose_response(&response_data);
+    }
+    Ok((response_data, trace))
+}
+
+/// Applies `shape_response` and attaches the trace, once per response handed
+/// back to the caller.
+fn finish_response(
+    req: &ProxyRequest,
+    response: &mut ProxyResponse,
+    mut trace: PhaseTrace,
+    config: &config::RuntimeConfig,
+) {
+    shape_response(req, response, config);
+    trace.mark("post_processing");
+    response.trace = req.trace.unwrap_or(false).then_some(trace.spans);
+}
+
+async fn cancel(token: web::Path<String>, state: web::Data<AppState>) -> HttpResponse {
+    let token = token.into_inner();
+    match state.cancellations.lock().unwrap().get(&token) {
+        Some(notify) => {
+            notify.notify_one();
+            HttpResponse::Ok().json(serde_json::json!({ "cancelled": token }))
+        }
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No in-flight request with cancel token '{}'", token)
+        })),
+    }
+}
+
+/// Status for the `/proxy` envelope. By default it is always 200; with
+/// `passthrough_status` it mirrors upstream, except for statuses the request
+/// declares as `success_statuses`.
+fn envelope_status(req: &ProxyRequest, upstream: u16) -> StatusCode {
+    if !req.passthrough_status
+        || req.success_statuses.as_ref().is_some_and(|s| s.contains(&upstream))
+    {
+        return StatusCode::OK;
+    }
+    StatusCode::from_u16(upstream).unwrap_or(StatusCode::BAD_GATEWAY)
+}
+
+async fn proxy(req: web::Json<ProxyRequest>, state: web::Data<AppState>) -> HttpResponse {
+    let chaos = state.chaos.roll();
+    let response = match chaos {
+        Some((chaos::ChaosMode::Error, _)) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Injected failure",
+            "error_kind": "chaos"
+        })),
+        _ => {
+            if let Some((chaos::ChaosMode::Latency, latency_ms)) = chaos {
+                tokio::time::sleep(Duration::from_millis(latency_ms)).await;
+            }
+            match execute_proxy(&req, &state).await {
+                Ok(response) => {
+                    let timing = req.emit_server_timing.unwrap_or(false).then(|| own_server_timing(&response));
+                    let mut reply = if req.raw_response.unwrap_or(false) {
+                        raw_response(response)
+                    } else if req.to_csv.unwrap_or(false) {
+                        csv_response(&req, response)
+                    } else {
+                        HttpResponse::build(envelope_status(&req, response.status)).json(response)
+                    };
+                    if let Some(timing) = timing.and_then(|t| HeaderValue::from_str(&t).ok()) {
+                        let headers = reply.headers_mut();
+                        headers.insert(HeaderName::from_static("server-timing"), timing);
+                        // Without this, browsers hide the metrics from cross-origin callers.
+                        headers.insert(HeaderName::from_static("timing-allow-origin"), HeaderValue::from_static("*"));
+                    }
+                    reply
+                }
+                Err(e) => e.to_response(),
+            }
+        }
+    };
+    match chaos {
+        Some((mode, _)) => inject_chaos(response, mode).await,
+        None => response,
+    }
+}
+
+/// Formats this request's own timings as a `Server-Timing` value. reqwest
+/// does not time DNS on its own, so `dns` is only reported, as 0, when the
+/// host was answered from the DNS cache.
+fn own_server_timing(response: &ProxyResponse) -> String {
+    let mut entries = vec![format!("total;dur={}", response.duration_ms)];
+    if response.dns_cache_hit == Some(true) {
+        entries.push("dns;dur=0;desc=\"cached\"".to_string());
+    }
+    if let Some(ttfb) = response.time_to_first_byte_ms {
+        entries.push(format!("ttfb;dur={}", ttfb));
+    }
+    entries.join(", ")
+}
+
+/// Marks a reply as affected by chaos, cutting its body in half for
+/// `truncate`.
+async fn inject_chaos(response: HttpResponse, mode: chaos::ChaosMode) -> HttpResponse {
+    info!("Injected chaos: {}", mode.as_str());
+    let mut response = if mode == chaos::ChaosMode::Truncate {
+        let (response, body) = response.into_parts();
+        let body = actix_web::body::to_bytes(body).await.unwrap_or_default();
+        response.set_body(body.slice(..body.len() / 2)).map_into_boxed_body()
+    } else {
+        response
+    };
+    response.headers_mut().insert(
+        actix_web::http::header::HeaderName::from_static("x-chaos-injected"),
+        actix_web::http::header::HeaderValue::from_static(mode.as_str()),
+    );
+    response
+}
+
+/// Replays the upstream response without the envelope. The body is sent
+/// as received, so `Content-Encoding` is forwarded along with the content
+/// type; if the body could not be read, the error is returned as JSON.
+fn raw_response(response: ProxyResponse) -> HttpResponse {
+    let Some(body) = response.upstream_body else {
+        return ProxyError::RequestFailed("Failed to read the response body".to_string()).to_response();
+    };
+    let mut builder = HttpResponse::build(StatusCode::from_u16(response.status).unwrap_or(StatusCode::BAD_GATEWAY));
+    for name in [CONTENT_TYPE, CONTENT_ENCODING] {
+        if let Some(value) = response.headers.get(name.as_str()) {
+            builder.insert_header((name.as_str(), value.as_str()));
+        }
+    }
+    builder.body(body)
+}
+
+/// Renders the body as CSV under the usual envelope status. A body that is
+/// not an array of objects cannot be tabulated and is reported as 422.
+fn csv_response(req: &ProxyRequest, response: ProxyResponse) -> HttpResponse {
+    match transforms::to_csv(&response.body) {
+        Ok(csv) => HttpResponse::build(envelope_status(req, response.status))
+            .content_type("text/csv; charset=utf-8")
+            .body(csv),
+        Err(e) => HttpResponse::UnprocessableEntity().json(serde_json::json!({ "error": e })),
+    }
+}
+
+fn validate_batch(req: &BatchRequest, config: &config::RuntimeConfig) -> Result<(), String> {
+    if req.requests.len() > config.max_batch_requests {
+        return Err(format!("Batch exceeds the limit of {} requests", config.max_batch_requests));
+    }
+    if req.sample_rate.is_some_and(|rate| !(0.0..=1.0).contains(&rate)) {
+        return Err("sample_rate must be between 0 and 1".to_string());
+    }
+    Ok(())
+}
+
+/// Executes the batch with bounded concurrency, yielding each result as soon
+/// as its request completes. Results therefore arrive out of order; `index`
+/// ties them back to the submitted list.
+fn run_batch(
+    req: BatchRequest,
+    state: web::Data<AppState>,
+) -> impl futures::Stream<Item = BatchItemResult> {
+    let concurrency = req.concurrency.unwrap_or(state.config().batch_concurrency).max(1);
+    let only_tags = req.only_tags;
+    let random_seed = req.random_seed;
+    futures::stream::iter(req.requests.into_iter().enumerate())
+        .filter(move |(_, request)| {
+            let selected = match &only_tags {
+                Some(wanted) => request
+                    .tags
+                    .as_ref()
+                    .is_some_and(|tags| tags.iter().any(|t| wanted.contains(t))),
+                None => true,
+            };
+            futures::future::ready(selected)
+        })
+        .map(move |(index, mut request)| {
+            if request.random_seed.is_none() {
+                request.random_seed = random_seed.map(|seed| seed.wrapping_add(index as u64));
+            }
+            let state = state.clone();
+            async move {
+                match execute_proxy(&request, &state).await {
+                    Ok(response) => BatchItemResult { index, response: Some(response), error: None },
+                    Err(e) => BatchItemResult { index, response: None, error: Some(e.message()) },
+                }
+            }
+        })
+        .buffer_unordered(concurrency)
+}
+
+async fn batch(req: web::Json<BatchRequest>, state: web::Data<AppState>) -> HttpResponse {
+    let start_time = std::time::Instant::now();
+    if let Err(e) = validate_batch(&req, &state.config()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+    }
+
+    let req = req.into_inner();
+    let request_tags: Vec<Vec<String>> = req
+        .requests
+        .iter()
+        .map(|r| r.tags.clone().unwrap_or_default())
+        .collect();
+    let (sample_rate, random_seed) = (req.sample_rate, req.random_seed);
+    let mut results: Vec<BatchItemResult> = run_batch(req, state).collect().await;
+    results.sort_by_key(|r| r.index);
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    let succeeded = results.len() - failed;
+    let by_tag = summarize_by_tag(&results, &request_tags);
+    let sampled_out = sample_rate.map(|rate| sample_results(&mut results, rate, random_seed));
+
+    HttpResponse::Ok().json(BatchResponse {
+        succeeded,
+        failed,
+        by_tag,
+        results,
+        duration_ms: start_time.elapsed().as_millis() as u64,
+        sampled_out,
+    })
+}
+
+/// Drops passing results at random, keeping each with probability
+/// `sample_rate`, and returns how many were dropped. Anything that would
+/// fail a collection run is always kept. With `random_seed`, the same
+/// results are kept on every run.
+fn sample_results(results: &mut Vec<BatchItemResult>, sample_rate: f64, random_seed: Option<u64>) -> usize {
+    let mut rng = match random_seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let before = results.len();
+    results.retain(|result| !collection_result_passed(result) || rng.gen_bool(sample_rate));
+    before - results.len()
+}
+
+/// Tallies results per tag. A request with several tags counts towards each,
+/// and passes by the same rule as a collection run, `collection_result_passed`.
+fn summarize_by_tag(
+    results: &[BatchItemResult],
+    request_tags: &[Vec<String>],
+) -> HashMap<String, TagSummary> {
+    let mut summaries: HashMap<String, TagSummary> = HashMap::new();
+    for result in results {
+        for tag in &request_tags[result.index] {
+            let summary = summaries.entry(tag.clone()).or_default();
+            summary.total += 1;
+            if collection_result_passed(result) {
+                summary.succeeded += 1;
+            } else {
+                summary.failed += 1;
+            }
+        }
+    }
+    for summary in summaries.values_mut() {
+        summary.pass_rate = summary.succeeded as f64 / summary.total as f64;
+    }
+    summaries
+}
+
+/// Streams batch results as newline-delimited JSON, one line per completed
+/// request, so clients can show progress before the whole batch finishes.
+async fn batch_stream(req: web::Json<BatchRequest>, state: web::Data<AppState>) -> HttpResponse {
+    if let Err(e) = validate_batch(&req, &state.config()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+    }
+
+    let lines = run_batch(req.into_inner(), state).map(|result| {
+        let mut line = serde_json::to_vec(&result).unwrap_or_default();
+        line.push(b'\n');
+        Ok::<_, actix_web::Error>(web::Bytes::from(line))
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(lines)
+}
+
+/// Whether a collection entry counts as passing when run from the command
+/// line: the request completed, the upstream did not answer with an error
+/// status, no latency baseline check flagged a regression, and every
+/// requested check passed.
+fn collection_result_passed(result: &BatchItemResult) -> bool {
+    result.response.as_ref().is_some_and(|response| {
+        response.status < 400
+            && response.latency_regression != Some(true)
+            && response.assertions_passed != Some(false)
+    })
+}
+
+/// Runs a collection file (the same JSON body `POST /batch` accepts) without
+/// starting the server, printing one line per request and a summary. Returns
+/// whether every request passed.
+async fn run_collection(path: &str, state: web::Data<AppState>) -> std::io::Result<bool> {
+    let contents = std::fs::read(path)
+        .map_err(|e| std::io::Error::new(e.kind(), format!("Cannot read collection {}: {}", path, e)))?;
+    let collection: BatchRequest = serde_json::from_slice(&contents).map_err(|e| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid collection {}: {}", path, e))
+    })?;
+    validate_batch(&collection, &state.config()).map_err(|e| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid collection {}: {}", path, e))
+    })?;
+
+    let start_time = std::time::Instant::now();
+    let labels: Vec<String> = collection
+        .requests
+        .iter()
+        .map(|r| match &r.targets {
+            Some(targets) => format!("{} <one of {} targets>", r.method, targets.len()),
+            None => format!("{} {}", r.method, r.url),
+        })
+        .collect();
+    let mut results: Vec<BatchItemResult> = run_batch(collection, state).collect().await;
+    results.sort_by_key(|r| r.index);
+
+    let mut passed = 0;
+    for result in &results {
+        let ok = collection_result_passed(result);
+        passed += usize::from(ok);
+        let outcome = match (&result.response, &result.error) {
+            (Some(response), _) => format!("{} ({} ms)", response.status, response.duration_ms),
+            (None, Some(error)) => error.clone(),
+            (None, None) => String::new(),
+        };
+        println!(
+            "{}  [{}] {} -> {}",
+            if ok { "PASS" } else { "FAIL" },
+            result.index,
+            labels[result.index],
+            outcome
+        );
+    }
+    let failed = results.len() - passed;
+    println!(
+        "{} passed, {} failed in {} ms",
+        passed,
+        failed,
+        start_time.elapsed().as_millis()
+    );
+    Ok(failed == 0)
+}
+
+/// Resolves `name` inside the directory named by `WS_SEND_FILE_DIR`, refusing
+/// anything that escapes it. File sending is disabled when the variable is unset.
+fn resolve_send_file(name: &str) -> Result<PathBuf, String> {
+    let dir = std::env::var("WS_SEND_FILE_DIR")
+        .map_err(|_| "send_file is disabled; set WS_SEND_FILE_DIR to enable it".to_string())?;
+    let dir = std::fs::canonicalize(&dir)
+        .map_err(|e| format!("Invalid WS_SEND_FILE_DIR: {}", e))?;
+    let path = std::fs::canonicalize(dir.join(name))
+        .map_err(|e| format!("Cannot open send_file '{}': {}", name, e))?;
+    if !path.starts_with(&dir) {
+        return Err(format!("send_file '{}' is outside the allowed directory", name));
+    }
+    Ok(path)
+}
+
+/// Splits file contents into WebSocket frames of at most `chunk_size` bytes.
+/// Text frames are cut on character boundaries so each one is valid UTF-8.
+fn file_frames(data: Vec<u8>, chunk_size: usize, binary: bool) -> Result<Vec<WsMessage>, String> {
+    let chunk_size = chunk_size.max(1);
+    if binary {
+        return Ok(data.chunks(chunk_size).map(|c| WsMessage::Binary(c.to_vec())).collect());
+    }
+
+    let text = String::from_utf8(data)
+        .map_err(|_| "send_file is not valid UTF-8; set binary_frames to send it".to_string())?;
+    let mut frames = Vec::new();
+    let mut rest = text.as_str();
+    while !rest.is_empty() {
+        let mut end = chunk_size.min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end += 1;
+        }
+        frames.push(WsMessage::Text(rest[..end].to_string()));
+        rest = &rest[end..];
+    }
+    Ok(frames)
+}
+
+/// Offers `permessage-deflate` in a WebSocket handshake and returns the
+/// extension header the server accepted, if any. The connection is closed
+/// straight away, before any possibly compressed frame arrives.
+async fn probe_ws_compression(url: &Url) -> Result<Option<String>, String> {
+    let mut request = url.as_str().into_client_request().map_err(|e| e.to_string())?;
+    request.headers_mut().insert(
+        "Sec-WebSocket-Extensions",
+        HeaderValue::from_static("permessage-deflate; client_max_window_bits"),
+    );
+    let (mut ws_stream, response) = connect_async(request).await.map_err(|e| e.to_string())?;
+    let _ = ws_stream.close(None).await;
+    Ok(response
+        .headers()
+        .get_all("Sec-WebSocket-Extensions")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find(|value| value.trim_start().starts_with("permessage-deflate"))
+        .map(str::to_string))
+}
+
+async fn websocket(req: web::Json<WebSocketRequest>, state: web::Data<AppState>) -> HttpResponse {
+    let start_time = std::time::Instant::now();
+
+    let _guard = match WsConnectionGuard::acquire(&state.ws_connections) {
+        Some(guard) => guard,
+        None => {
+            return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": "Too many concurrent WebSocket connections"
+            }));
+        }
+    };
+
+    let url = match Url::parse(&req.url) {
+        Ok(url) => url,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid WebSocket URL: {}", e)
+            }));
+        }
+    };
+
+    let file_frames = match &req.send_file {
+        Some(name) => {
+            let chunk_size = req.chunk_size.unwrap_or(state.config().ws_file_chunk_size);
+            let frames = resolve_send_file(name)
+                .and_then(|path| std::fs::read(path).map_err(|e| e.to_string()))
+                .and_then(|data| file_frames(data, chunk_size, req.binary_frames));
+            match frames {
+                Ok(frames) => Some(frames),
+                Err(e) => {
+                    return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+                }
+            }
+        }
+        None => None,
+    };
+
+    let mut step_patterns = Vec::new();
+    for step in req.steps.iter().flatten() {
+        match step.wait_for.as_deref().map(Regex::new).transpose() {
+            Ok(pattern) => step_patterns.push(pattern),
+            Err(e) => {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Invalid wait_for pattern: {}", e)
+                }));
+            }
+        }
+    }
+
+    state.record_host(url.as_str());
+    let compression_extension = if req.enable_compression.unwrap_or(false) {
+        match probe_ws_compression(&url).await {
+            Ok(extension) => Some(extension),
+            Err(e) => {
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": format!("WebSocket connection failed: {}", e)
+                }));
+            }
+        }
+    } else {
+        None
+    };
+    let (ws_stream, _) = match connect_async(url).await {
+        Ok(conn) => conn,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("WebSocket connection failed: {}", e)
+            }));
+        }
+    };
+
+    let (mut write, mut read) = ws_stream.split();
+    let mut messages = Vec::new();
+
+    for msg in &req.messages {
+        match write.send(WsMessage::Text(msg.clone())).await {
+            Ok(_) => {
+                messages.push(WebSocketMessage {
+                    direction: "sent".to_string(),
+                    content: msg.clone(),
+                    timestamp: Utc::now().to_rfc3339(),
+                });
+            }
+            Err(e) => {
+                error!("Failed to send WebSocket message: {}", e);
+                break;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let mut step_results = None;
+    if let Some(steps) = &req.steps {
+        let mut results = Vec::with_capacity(steps.len());
+        for (step, pattern) in steps.iter().zip(&step_patterns) {
+            let sent_at = std::time::Instant::now();
+            let mut result = WebSocketStepResult { sent: step.send.clone(), reply: None, elapsed_ms: 0, error: None };
+            if let Err(e) = write.send(WsMessage::Text(step.send.clone())).await {
+                result.error = Some(format!("Send failed: {}", e));
+                results.push(result);
+                break;
+            }
+            messages.push(WebSocketMessage {
+                direction: "sent".to_string(),
+                content: step.send.clone(),
+                timestamp: Utc::now().to_rfc3339(),
+            });
+            let timeout = Duration::from_millis(step.timeout_ms.unwrap_or(DEFAULT_WS_STEP_TIMEOUT_MS));
+            let reply = tokio::time::timeout(timeout, async {
+                while let Some(message) = read.next().await {
+                    let msg = message.map_err(|e| format!("Receive failed: {}", e))?;
+                    let Ok(text) = msg.to_text() else {
+                        continue;
+                    };
+                    messages.push(WebSocketMessage {
+                        direction: "received".to_string(),
+                        content: text.to_string(),
+                        timestamp: Utc::now().to_rfc3339(),
+                    });
+                    if pattern.as_ref().is_none_or(|pattern| pattern.is_match(text)) {
+                        return Ok(text.to_string());
+                    }
+                }
+                Err("Connection closed before a reply".to_string())
+            })
+            .await;
+            result.elapsed_ms = sent_at.elapsed().as_millis() as u64;
+            match reply {
+                Ok(Ok(text)) => result.reply = Some(text),
+                Ok(Err(e)) => result.error = Some(e),
+                Err(_) => result.error = Some(format!("No reply within {}ms", timeout.as_millis())),
+            }
+            let failed = result.error.is_some();
+            results.push(result);
+            if failed {
+                break;
+            }
+        }
+        step_results = Some(results);
+    }
+
+    let mut bytes_sent = None;
+    let mut frame_count = None;
+    if let Some(frames) = file_frames {
+        let (mut bytes, mut count) = (0, 0);
+        for frame in frames {
+            let len = frame.len();
+            if let Err(e) = write.send(frame).await {
+                error!("Failed to send WebSocket file frame: {}", e);
+                break;
+            }
+            bytes += len;
+            count += 1;
+        }
+        bytes_sent = Some(bytes);
+        frame_count = Some(count);
+    }
+
+    let timeout = Duration::from_secs(req.duration.unwrap_or(5));
+    let _ = tokio::time::timeout(timeout, async {
+        while let Some(message) = read.next().await {
+            match message {
+                Ok(msg) => {
+                    if let Ok(text) = msg.to_text() {
+                        messages.push(WebSocketMessage {
+                            direction: "received".to_string(),
+                            content: text.to_string(),
+                            timestamp: Utc::now().to_rfc3339(),
+                        });
+                    }
+                }
+                Err(e) => {
+                    error!("WebSocket receive error: {}", e);
+                    break;
+                }
+            }
+        }
+    }).await;
+
+    HttpResponse::Ok().json(WebSocketResponse {
+        messages,
+        status: "completed".to_string(),
+        duration_ms: start_time.elapsed().as_millis() as u64,
+        bytes_sent,
+        frame_count,
+        steps: step_results,
+        compression_negotiated: compression_extension.as_ref().map(Option::is_some),
+        compression_extension: compression_extension.flatten(),
+    })
+}
+
+/// Measures WebSocket round-trip latency against an echo server. Messages are
+/// sent one at a time, each carrying a sequence number and timestamp, and the
+/// next is only sent once the identical text has come back or
+/// `WS_BENCH_REPLY_TIMEOUT` has passed. Unrelated frames are ignored.
+async fn websocket_bench(req: web::Json<WebSocketBenchRequest>, state: web::Data<AppState>) -> HttpResponse {
+    let start_time = std::time::Instant::now();
+    let message_count = req.message_count.unwrap_or(DEFAULT_WS_BENCH_MESSAGES);
+    if message_count > MAX_WS_BENCH_MESSAGES {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("message_count exceeds the limit of {}", MAX_WS_BENCH_MESSAGES)
+        }));
+    }
+
+    let _guard = match WsConnectionGuard::acquire(&state.ws_connections) {
+        Some(guard) => guard,
+        None => {
+            return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": "Too many concurrent WebSocket connections"
+            }));
+        }
+    };
+
+    let url = match Url::parse(&req.url) {
+        Ok(url) => url,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid WebSocket URL: {}", e)
+            }));
+        }
+    };
+
+    state.record_host(url.as_str());
+    let (ws_stream, _) = match connect_async(url).await {
+        Ok(conn) => conn,
+        Err(e) => {
             return HttpResponse::InternalServerError().json(serde_json::json!({
                 "error": format!("WebSocket connection failed: {}", e)
             }));
         }
     };
-
     let (mut write, mut read) = ws_stream.split();
-    let mut messages = Vec::new();
 
-    for msg in &req.messages {
-        match write.send(tokio_tungstenite::tungstenite::Message::Text(msg.clone())).await {
-            Ok(_) => {
-                messages.push(WebSocketMessage {
-                    direction: "sent".to_string(),
-                    content: msg.clone(),
-                    timestamp: Utc::now().to_rfc3339(),
-                });
+    let interval = Duration::from_millis(req.interval_ms.unwrap_or(0));
+    let mut latencies = Vec::with_capacity(message_count);
+    let mut sent = 0;
+    for seq in 0..message_count {
+        if seq > 0 && !interval.is_zero() {
+            tokio::time::sleep(interval).await;
+        }
+        let payload = serde_json::json!({ "seq": seq, "sent_at": Utc::now().to_rfc3339() }).to_string();
+        let sent_at = std::time::Instant::now();
+        if let Err(e) = write.send(WsMessage::Text(payload.clone())).await {
+            error!("Failed to send WebSocket bench message: {}", e);
+            break;
+        }
+        sent += 1;
+
+        let reply = tokio::time::timeout(WS_BENCH_REPLY_TIMEOUT, async {
+            while let Some(message) = read.next().await {
+                match message {
+                    Ok(msg) if msg.to_text().is_ok_and(|text| text == payload) => return true,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        error!("WebSocket receive error: {}", e);
+                        return false;
+                    }
+                }
+            }
+            false
+        })
+        .await;
+        match reply {
+            Ok(true) => latencies.push(sent_at.elapsed().as_secs_f64() * 1000.0),
+            Ok(false) => break,
+            Err(_) => warn!("No echo for WebSocket bench message {}", seq),
+        }
+    }
+    let _ = write.close().await;
+
+    let received = latencies.len();
+    HttpResponse::Ok().json(WebSocketBenchResponse {
+        sent,
+        received,
+        lost: sent - received,
+        latency: latency_stats(&mut latencies),
+        duration_ms: start_time.elapsed().as_millis() as u64,
+    })
+}
+
+enum GraphQLError {
+    /// The request never produced a response.
+    Transport(String),
+    /// A response arrived but was not a JSON GraphQL document.
+    Parse(String),
+}
+
+impl GraphQLError {
+    fn message(&self) -> String {
+        match self {
+            GraphQLError::Transport(e) => format!("GraphQL request failed: {}", e),
+            GraphQLError::Parse(e) => format!("Failed to parse GraphQL response: {}", e),
+        }
+    }
+}
+
+async fn execute_graphql(req: &GraphQLRequest, state: &AppState) -> Result<serde_json::Value, GraphQLError> {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        HeaderName::from_static("content-type"),
+        HeaderValue::from_static("application/json"),
+    );
+
+    insert_headers(&mut headers, &state.injected_headers.read().unwrap());
+    if let Some(custom_headers) = &req.headers {
+        insert_headers(&mut headers, custom_headers);
+    }
+
+    let body = serde_json::json!({
+        "query": req.query,
+        "variables": req.variables
+    });
+
+    state.record_host(&req.url);
+    let response = state.client.post(&req.url)
+        .timeout(state.config().request_timeout())
+        .headers(headers)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| GraphQLError::Transport(e.to_string()))?;
+    response
+        .json::<serde_json::Value>()
+        .await
+        .map_err(|e| GraphQLError::Parse(e.to_string()))
+}
+
+async fn graphql(req: web::Json<GraphQLRequest>, state: web::Data<AppState>) -> HttpResponse {
+    let start_time = std::time::Instant::now();
+
+    match execute_graphql(&req, &state).await {
+        Ok(gql_response) => HttpResponse::Ok().json(GraphQLResponse {
+            data: gql_response.get("data").cloned(),
+            errors: gql_response.get("errors").and_then(|e| e.as_array().cloned()),
+            duration_ms: start_time.elapsed().as_millis() as u64,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": e.message()
+        })),
+    }
+}
+
+/// A GraphQL server answers any well-formed POST with a JSON object holding
+/// `data` or an `errors` array, even when the query itself is rejected.
+fn looks_like_graphql(response: &serde_json::Value) -> bool {
+    response.get("data").is_some() || response.get("errors").is_some_and(|e| e.is_array())
+}
+
+/// Probes an unknown URL to guess what kind of API it serves: an OPTIONS
+/// request for the allowed methods, a GET for the content type, and a
+/// `{ __typename }` GraphQL query, followed by an introspection query if the
+/// URL turns out to be GraphQL.
+async fn detect(req: web::Json<DetectRequest>, state: web::Data<AppState>) -> HttpResponse {
+    let start_time = std::time::Instant::now();
+    if let Err(e) = Url::parse(&req.url) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Invalid URL: {}", e)
+        }));
+    }
+
+    let mut headers = HeaderMap::new();
+    insert_headers(&mut headers, &state.injected_headers.read().unwrap());
+    if let Some(custom_headers) = &req.headers {
+        insert_headers(&mut headers, custom_headers);
+    }
+    let timeout = state.config().request_timeout();
+    let graphql_request = |query: &str| GraphQLRequest {
+        url: req.url.clone(),
+        query: query.to_string(),
+        variables: None,
+        headers: req.headers.clone(),
+    };
+    let ping = graphql_request("{ __typename }");
+
+    let (options, get, ping) = tokio::join!(
+        state
+            .client
+            .request(reqwest::Method::OPTIONS, &req.url)
+            .headers(headers.clone())
+            .timeout(timeout)
+            .send(),
+        state.client.get(&req.url).headers(headers).timeout(timeout).send(),
+        execute_graphql(&ping, &state)
+    );
+    if options.is_err() && get.is_err() && matches!(ping, Err(GraphQLError::Transport(_))) {
+        let e = get.err().map(|e| e.to_string()).unwrap_or_default();
+        return HttpResponse::BadGateway().json(serde_json::json!({
+            "error": format!("Request failed: {}", e)
+        }));
+    }
+
+    let mut methods: Vec<String> = Vec::new();
+    if let Ok(response) = &options {
+        for name in [reqwest::header::ALLOW, reqwest::header::ACCESS_CONTROL_ALLOW_METHODS] {
+            for value in response.headers().get_all(name) {
+                for method in value.to_str().unwrap_or("").split(',') {
+                    let method = method.trim().to_uppercase();
+                    if !method.is_empty() && !methods.contains(&method) {
+                        methods.push(method);
+                    }
+                }
+            }
+        }
+    }
+    let content_type = get.as_ref().ok().and_then(|response| {
+        response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    });
+
+    let is_graphql = ping.as_ref().is_ok_and(looks_like_graphql);
+    let introspection_enabled = if is_graphql {
+        let introspection = graphql_request("{ __schema { queryType { name } } }");
+        let response = execute_graphql(&introspection, &state).await;
+        Some(response.is_ok_and(|r| r.pointer("/data/__schema").is_some_and(|schema| !schema.is_null())))
+    } else {
+        None
+    };
+    let api_type = if is_graphql {
+        "graphql"
+    } else if content_type.as_deref().is_some_and(|ct| ct.to_ascii_lowercase().contains("json")) {
+        "rest"
+    } else if get.is_ok() {
+        "other"
+    } else {
+        "unknown"
+    };
+
+    HttpResponse::Ok().json(DetectResponse {
+        url: req.url.clone(),
+        api_type,
+        methods,
+        introspection_enabled,
+        content_type,
+        duration_ms: start_time.elapsed().as_millis() as u64,
+    })
+}
+
+/// Nearest-rank percentile of an ascending slice.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn latency_stats(samples_ms: &mut [f64]) -> Option<LatencyStats> {
+    if samples_ms.is_empty() {
+        return None;
+    }
+    samples_ms.sort_by(|a, b| a.total_cmp(b));
+    Some(LatencyStats {
+        min_ms: samples_ms[0],
+        mean_ms: samples_ms.iter().sum::<f64>() / samples_ms.len() as f64,
+        p50_ms: percentile(samples_ms, 50.0),
+        p90_ms: percentile(samples_ms, 90.0),
+        p95_ms: percentile(samples_ms, 95.0),
+        p99_ms: percentile(samples_ms, 99.0),
+        max_ms: samples_ms[samples_ms.len() - 1],
+    })
+}
+
+/// Outcome counts and latency samples for a set of load test requests.
+#[derive(Default)]
+struct LoadTestTally {
+    total: usize,
+    succeeded: usize,
+    transport_errors: usize,
+    graphql_errors: usize,
+    latencies: Vec<f64>,
+    warmup_latencies: Vec<f64>,
+}
+
+impl LoadTestTally {
+    fn add(&mut self, warmup: bool, latency: Duration, result: &Result<serde_json::Value, GraphQLError>) {
+        self.total += 1;
+        match result {
+            Ok(body) => {
+                let latency_ms = latency.as_secs_f64() * 1000.0;
+                if warmup {
+                    self.warmup_latencies.push(latency_ms);
+                } else {
+                    self.latencies.push(latency_ms);
+                }
+                let has_errors = body
+                    .get("errors")
+                    .and_then(|e| e.as_array())
+                    .is_some_and(|e| !e.is_empty());
+                if has_errors {
+                    self.graphql_errors += 1;
+                } else {
+                    self.succeeded += 1;
+                }
+            }
+            Err(_) => self.transport_errors += 1,
+        }
+    }
+}
+
+/// Fires `total` GraphQL requests with bounded concurrency and reports
+/// latency percentiles. Transport failures and responses carrying a GraphQL
+/// `errors` array are counted separately. With `warmup_count`, the first
+/// requests issued are left out of `latency` so cold connections do not skew
+/// the steady-state numbers; they still count towards the totals.
+///
+/// The body is either a single request, repeated `total` times, or a weighted
+/// `requests` mix sampled independently for every iteration.
+async fn graphql_loadtest(req: web::Json<GraphQLLoadTestRequest>, state: web::Data<AppState>) -> HttpResponse {
+    let config = state.config();
+    let total = req.total.unwrap_or(config.loadtest_total);
+    if total > config.max_loadtest_requests {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("total exceeds the limit of {} requests", config.max_loadtest_requests)
+        }));
+    }
+    let concurrency = req.concurrency.unwrap_or(config.batch_concurrency).max(1);
+
+    let (requests, choices): (Vec<&GraphQLRequest>, Vec<usize>) = match (&req.request, &req.requests) {
+        (Some(request), None) => (vec![request], vec![0; total]),
+        (None, Some(mix)) => {
+            let weights = match WeightedIndex::new(mix.iter().map(|entry| entry.weight)) {
+                Ok(weights) => weights,
+                Err(e) => {
+                    return HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": format!("Invalid request weights: {}", e)
+                    }))
+                }
+            };
+            let mut rng = rand::thread_rng();
+            let choices = (0..total).map(|_| weights.sample(&mut rng)).collect();
+            (mix.iter().map(|entry| &entry.request).collect(), choices)
+        }
+        _ => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Provide either a single request (url and query) or a weighted requests list"
+            }))
+        }
+    };
+
+    let start_time = std::time::Instant::now();
+    let warmup_count = req.warmup_count.unwrap_or(0);
+    let outcomes: Vec<(usize, usize, Duration, Result<serde_json::Value, GraphQLError>)> =
+        futures::stream::iter(choices.into_iter().enumerate())
+            .map(|(index, choice)| {
+                let (request, state) = (requests[choice], &state);
+                async move {
+                    let request_start = std::time::Instant::now();
+                    let result = execute_graphql(request, state).await;
+                    (index, choice, request_start.elapsed(), result)
+                }
+            })
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+    let elapsed = start_time.elapsed();
+
+    let mut overall = LoadTestTally::default();
+    let mut per_request: Vec<LoadTestTally> = requests.iter().map(|_| LoadTestTally::default()).collect();
+    for (index, choice, latency, result) in &outcomes {
+        let warmup = *index < warmup_count;
+        overall.add(warmup, *latency, result);
+        per_request[*choice].add(warmup, *latency, result);
+    }
+    let by_request = req.requests.as_ref().map(|mix| {
+        mix.iter()
+            .zip(per_request)
+            .enumerate()
+            .map(|(index, (entry, mut tally))| LoadTestMixStats {
+                index,
+                weight: entry.weight,
+                total: tally.total,
+                succeeded: tally.succeeded,
+                transport_errors: tally.transport_errors,
+                graphql_errors: tally.graphql_errors,
+                latency: latency_stats(&mut tally.latencies),
+            })
+            .collect()
+    });
+
+    HttpResponse::Ok().json(GraphQLLoadTestResponse {
+        total,
+        succeeded: overall.succeeded,
+        transport_errors: overall.transport_errors,
+        graphql_errors: overall.graphql_errors,
+        error_rate: if total == 0 { 0.0 } else { (total - overall.succeeded) as f64 / total as f64 },
+        latency: latency_stats(&mut overall.latencies),
+        warmup_count: req.warmup_count,
+        warmup_latency: latency_stats(&mut overall.warmup_latencies),
+        duration_ms: elapsed.as_millis() as u64,
+        requests_per_second: total as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        by_request,
+    })
+}
+
+/// Opens `count` cold connections to `host:port`, each with a full TLS
+/// handshake and no session resumption, and reports how long connecting and
+/// handshaking took. No HTTP request is sent.
+async fn tls_bench(req: web::Json<TlsBenchRequest>, state: web::Data<AppState>) -> HttpResponse {
+    let config = state.config();
+    let total = req.count.unwrap_or(config.loadtest_total);
+    if total > config.max_loadtest_requests {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("count exceeds the limit of {} handshakes", config.max_loadtest_requests)
+        }));
+    }
+    let concurrency = req.concurrency.unwrap_or(config.batch_concurrency).max(1);
+
+    let start_time = std::time::Instant::now();
+    let outcomes: Vec<Result<tls::HandshakeTiming, String>> = futures::stream::iter(0..total)
+        .map(|_| tls::cold_handshake(&req.host, req.port))
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+    let elapsed = start_time.elapsed();
+
+    let (mut latencies, mut connects, mut handshakes) = (Vec::new(), Vec::new(), Vec::new());
+    let mut errors = BTreeMap::new();
+    for outcome in outcomes {
+        match outcome {
+            Ok(timing) => {
+                latencies.push((timing.connect + timing.handshake).as_secs_f64() * 1000.0);
+                connects.push(timing.connect.as_secs_f64() * 1000.0);
+                handshakes.push(timing.handshake.as_secs_f64() * 1000.0);
+            }
+            Err(e) => *errors.entry(e).or_insert(0) += 1,
+        }
+    }
+
+    let succeeded = latencies.len();
+    HttpResponse::Ok().json(TlsBenchResponse {
+        total,
+        succeeded,
+        failed: total - succeeded,
+        errors,
+        latency: latency_stats(&mut latencies),
+        connect: latency_stats(&mut connects),
+        handshake: latency_stats(&mut handshakes),
+        duration_ms: elapsed.as_millis() as u64,
+        handshakes_per_second: succeeded as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+    })
+}
+
+fn decode_input(input: &str, encoding: &str) -> Result<Vec<u8>, String> {
+    match encoding {
+        "utf8" => Ok(input.as_bytes().to_vec()),
+        "hex" => hex::decode(input.trim()).map_err(|e| format!("Invalid hex input: {}", e)),
+        "base64" => BASE64
+            .decode(input.trim())
+            .or_else(|_| URL_SAFE.decode(input.trim()))
+            .map_err(|e| format!("Invalid base64 input: {}", e)),
+        other => Err(format!("Unsupported encoding: {}", other)),
+    }
+}
+
+fn encode_output(bytes: Vec<u8>, encoding: &str) -> Result<String, String> {
+    match encoding {
+        "utf8" => String::from_utf8(bytes).map_err(|_| "Decoded bytes are not valid UTF-8".to_string()),
+        "hex" => Ok(hex::encode(bytes)),
+        "base64" => Ok(BASE64.encode(bytes)),
+        other => Err(format!("Unsupported encoding: {}", other)),
+    }
+}
+
+/// Converts a value between base64, hex and UTF-8 text, for inspecting
+/// binary fields returned by APIs under test.
+async fn convert(req: web::Json<ConvertRequest>) -> HttpResponse {
+    let result = decode_input(&req.input, &req.from).and_then(|bytes| {
+        let len = bytes.len();
+        encode_output(bytes, &req.to).map(|output| (output, len))
+    });
+
+    match result {
+        Ok((output, bytes)) => HttpResponse::Ok().json(serde_json::json!({
+            "output": output,
+            "from": req.from,
+            "to": req.to,
+            "bytes": bytes
+        })),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    }
+}
+
+/// Compares two OpenAPI documents and lists the changes between them, each
+/// flagged as breaking or non-breaking. `breaking` is true when any change
+/// would break existing clients, for use as a CI gate.
+async fn openapi_diff(req: web::Json<OpenApiDiffRequest>) -> HttpResponse {
+    let (old, new) = match (openapi::load(&req.old), openapi::load(&req.new)) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("Invalid old spec: {}", e) }))
+        }
+        (_, Err(e)) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("Invalid new spec: {}", e) }))
+        }
+    };
+
+    let changes = openapi::diff(&old, &new);
+    let breaking = changes.iter().filter(|c| c.severity == openapi::Severity::Breaking).count();
+    HttpResponse::Ok().json(serde_json::json!({
+        "breaking": breaking > 0,
+        "summary": {
+            "breaking": breaking,
+            "non_breaking": changes.len() - breaking
+        },
+        "changes": changes
+    }))
+}
+
+/// Requests every GET operation in an OpenAPI spec through the proxy, with
+/// `use_cache` set so the responses also warm the cache, and reports which
+/// ones answered with an error status. Path and query parameters take their
+/// values from `parameters` or the spec's examples; operations missing a
+/// required value are skipped.
+async fn import_openapi(req: web::Json<OpenApiImportRequest>, state: web::Data<AppState>) -> HttpResponse {
+    let start_time = std::time::Instant::now();
+    let doc = match openapi::load(&req.spec) {
+        Ok(doc) => doc,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("Invalid spec: {}", e) }))
+        }
+    };
+    let Some(base_url) = req.base_url.clone().or_else(|| openapi::base_url(&doc)) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "The spec declares no absolute server URL; pass base_url"
+        }));
+    };
+    let base_url = base_url.trim_end_matches('/').to_string();
+
+    let mut checks = Vec::new();
+    let mut requests = Vec::new();
+    let mut request_checks = Vec::new();
+    for op in openapi::get_operations(&doc, &req.parameters) {
+        let mut check = OperationCheck {
+            path: op.path,
+            operation_id: op.operation_id,
+            url: None,
+            status: None,
+            duration_ms: None,
+            passed: false,
+            error: None,
+            missing_parameters: op.missing,
+        };
+        if let Some(resolved_path) = op.resolved_path {
+            match Url::parse(&format!("{}{}", base_url, resolved_path)) {
+                Ok(mut url) => {
+                    if !op.query.is_empty() {
+                        url.query_pairs_mut().extend_pairs(&op.query);
+                    }
+                    let request = serde_json::from_value::<ProxyRequest>(serde_json::json!({
+                        "url": url.as_str(),
+                        "method": "GET",
+                        "headers": req.headers,
+                        "use_cache": true,
+                    }))
+                    .expect("a GET request built from a URL is valid");
+                    check.url = Some(url.into());
+                    request_checks.push(checks.len());
+                    requests.push(request);
+                }
+                Err(e) => check.error = Some(format!("Invalid URL: {}", e)),
+            }
+        }
+        checks.push(check);
+    }
+
+    let batch = BatchRequest {
+        requests,
+        concurrency: req.concurrency,
+        only_tags: None,
+        random_seed: None,
+        sample_rate: None,
+    };
+    if let Err(e) = validate_batch(&batch, &state.config()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+    }
+    let results: Vec<BatchItemResult> = run_batch(batch, state).collect().await;
+    for result in results {
+        let check = &mut checks[request_checks[result.index]];
+        match result.response {
+            Some(response) => {
+                check.passed = response.status < 400;
+                check.status = Some(response.status);
+                check.duration_ms = Some(response.duration_ms);
             }
+            None => check.error = result.error,
+        }
+    }
+
+    let skipped = checks.iter().filter(|c| !c.missing_parameters.is_empty()).count();
+    let passed = checks.iter().filter(|c| c.passed).count();
+    HttpResponse::Ok().json(OpenApiImportResponse {
+        base_url,
+        total: checks.len(),
+        passed,
+        failed: checks.len() - passed - skipped,
+        skipped,
+        operations: checks,
+        duration_ms: start_time.elapsed().as_millis() as u64,
+    })
+}
+
+/// Describes what this build of the tester supports, so clients can render
+/// matching controls. Lists come from the same constants the handlers check
+/// against; defaults are the built-in values before any configuration is
+/// applied (see `GET /config` for the values in effect).
+#[get("/capabilities")]
+async fn capabilities() -> HttpResponse {
+    let mut defaults = serde_json::to_value(config::RuntimeConfig::default()).unwrap_or_default();
+    defaults["cacheable_statuses"] = serde_json::json!("2xx");
+    HttpResponse::Ok().json(serde_json::json!({
+        "methods": SUPPORTED_METHODS,
+        "request_body_types": REQUEST_BODY_TYPES,
+        "response_body_types": RESPONSE_BODY_TYPES,
+        "request_compression": REQUEST_COMPRESSIONS,
+        "response_encodings": RESPONSE_ENCODINGS,
+        "min_tls_versions": MIN_TLS_VERSIONS,
+        "http_versions": HTTP_VERSIONS,
+        "patch_types": PATCH_TYPES,
+        "convert_encodings": CONVERT_ENCODINGS,
+        "interceptors": interceptors::KINDS,
+        "pipeline_transforms": transforms::KINDS,
+        "assertion_ops": assertions::OPS,
+        "assertion_operands": assertions::OPERANDS,
+        "transforms": BODY_SHAPING_OPTIONS,
+        "features": {
+            "redis_cache": cfg!(feature = "redis"),
+        },
+        "defaults": defaults,
+    }))
+}
+
+/// Accepts a multipart upload and stores each file part temporarily. The
+/// returned ids can be passed as `body_upload_id` to stream a file to a
+/// target; unused uploads expire after `UPLOAD_TTL`.
+async fn upload(mut payload: actix_multipart::Multipart, state: web::Data<AppState>) -> HttpResponse {
+    let mut stored = Vec::new();
+    while let Some(field) = payload.next().await {
+        let field = match field {
+            Ok(field) => field,
             Err(e) => {
-                error!("Failed to send WebSocket message: {}", e);
-                break;
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Invalid multipart body: {}", e)
+                }))
+            }
+        };
+        let Some(filename) = field.content_disposition().get_filename().map(str::to_string) else {
+            continue;
+        };
+        let content_type = field.content_type().map(|m| m.to_string());
+        match state.uploads.save(Some(filename), content_type, field).await {
+            Ok(upload) => stored.push(upload),
+            Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+        }
+    }
+
+    if stored.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "No file parts found in upload"
+        }));
+    }
+    info!("Stored {} upload(s)", stored.len());
+    HttpResponse::Ok().json(serde_json::json!({
+        "uploads": stored,
+        "expires_in_secs": uploads::UPLOAD_TTL.as_secs()
+    }))
+}
+
+/// Sends one request to several regional deployments at once and reports
+/// their status and latency side by side. The cache is bypassed so every
+/// region is actually measured.
+async fn multiregion(req: web::Json<MultiRegionRequest>, state: web::Data<AppState>) -> HttpResponse {
+    if req.regions.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "regions must not be empty" }));
+    }
+    if req.regions.len() > state.config().max_batch_requests {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("regions exceeds the limit of {}", state.config().max_batch_requests)
+        }));
+    }
+    if req.request.targets.is_some() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "request cannot use targets; list the URLs as regions"
+        }));
+    }
+    if Url::parse(&req.request.url).is_ok() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "request url must be a path, such as /v1/status, not an absolute URL"
+        }));
+    }
+
+    let results: Vec<RegionResult> = futures::future::join_all(req.regions.iter().map(|region| {
+        let url = format!("{}{}", region.base_url.trim_end_matches('/'), req.request.url);
+        let request = ProxyRequest { url: url.clone(), use_cache: false, ..req.request.clone() };
+        let state = &state;
+        async move {
+            let result = execute_proxy(&request, state).await;
+            let (status, duration_ms, error) = match result {
+                Ok(response) => (Some(response.status), Some(response.duration_ms), None),
+                Err(e) => (None, None, Some(e.message())),
+            };
+            RegionResult {
+                region: region.name.clone().unwrap_or_else(|| region.base_url.clone()),
+                url,
+                status,
+                duration_ms,
+                error,
+            }
+        }
+    }))
+    .await;
+
+    let responded = || results.iter().filter_map(|r| r.duration_ms.map(|ms| (ms, &r.region)));
+    HttpResponse::Ok().json(MultiRegionResponse {
+        fastest: responded().min_by_key(|(ms, _)| *ms).map(|(_, region)| region.clone()),
+        slowest: responded().max_by_key(|(ms, _)| *ms).map(|(_, region)| region.clone()),
+        results,
+    })
+}
+
+/// Runs a request `samples` times, one after another, and stores the mean
+/// and standard deviation of its latency under `name`. The cache is bypassed
+/// so every sample reaches the target.
+async fn record_latency_baseline(
+    name: web::Path<String>,
+    req: web::Json<RecordBaselineRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Err(e) = baselines::validate_name(&name) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+    }
+    let samples = req.samples.unwrap_or(DEFAULT_BASELINE_SAMPLES);
+    if samples == 0 || samples > MAX_BASELINE_SAMPLES {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("samples must be between 1 and {}", MAX_BASELINE_SAMPLES)
+        }));
+    }
+    let request = ProxyRequest { use_cache: false, check_latency_baseline: None, ..req.request.clone() };
+    let mut durations = Vec::with_capacity(samples);
+    for _ in 0..samples {
+        match execute_proxy(&request, &state).await {
+            Ok(response) => durations.push(response.duration_ms as f64),
+            Err(e) => return e.to_response(),
+        }
+    }
+
+    let baseline = baselines::LatencyBaseline::from_samples(&durations);
+    if let Err(e) = state.baselines.set_latency(&name, baseline.clone()) {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e }));
+    }
+    info!("Recorded latency baseline '{}': {:.1} ms mean", name, baseline.mean_ms);
+    HttpResponse::Ok().json(serde_json::json!({ "name": name.into_inner(), "baseline": baseline }))
+}
+
+/// Schedules a request to run once, later, returning its job id at once.
+/// The outcome is kept for `GET /jobs/{id}`.
+async fn schedule_once(req: web::Json<ScheduleOnceRequest>, state: web::Data<AppState>) -> HttpResponse {
+    let req = req.into_inner();
+    let delay = match (req.delay_ms, &req.run_at) {
+        (Some(delay_ms), None) => Duration::from_millis(delay_ms),
+        (None, Some(run_at)) => match chrono::DateTime::parse_from_rfc3339(run_at) {
+            Ok(run_at) => (run_at.with_timezone(&Utc) - Utc::now()).to_std().unwrap_or(Duration::ZERO),
+            Err(e) => {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Invalid run_at: {}", e)
+                }));
             }
+        },
+        _ => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Give exactly one of delay_ms and run_at"
+            }));
+        }
+    };
+    if delay > jobs::MAX_SCHEDULE_DELAY {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Jobs can be scheduled at most {} seconds ahead", jobs::MAX_SCHEDULE_DELAY.as_secs())
+        }));
+    }
+
+    let run_at = Utc::now() + chrono::Duration::from_std(delay).unwrap_or_default();
+    let job = state.jobs.add(generate_request_id(), run_at.to_rfc3339());
+    let job_id = job.job_id.clone();
+    info!("Scheduled job {} for {}", job_id, job.run_at);
+    let state = state.clone();
+    actix_web::rt::spawn(async move {
+        tokio::time::sleep(delay).await;
+        state.jobs.set_status(&job_id, jobs::JobStatus::Running);
+        let status = match execute_proxy(&req.request, &state).await {
+            Ok(result) => jobs::JobStatus::Completed { result },
+            Err(e) => jobs::JobStatus::Failed { error: e.message() },
+        };
+        state.jobs.set_status(&job_id, status);
+    });
+    HttpResponse::Accepted().json(job)
+}
+
+async fn get_job(job_id: web::Path<String>, state: web::Data<AppState>) -> HttpResponse {
+    match state.jobs.get(&job_id) {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Unknown job '{}'", job_id)
+        })),
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct StartRunRequest {
+    name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct RunSummary {
+    #[serde(flatten)]
+    info: runs::RunInfo,
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+    by_status: std::collections::BTreeMap<u16, usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency: Option<LatencyStats>,
+}
+
+/// Starts a named run. The body, with an optional `name`, may be omitted.
+async fn start_run(req: Option<web::Json<StartRunRequest>>, state: web::Data<AppState>) -> HttpResponse {
+    let name = req.map(|req| req.into_inner()).unwrap_or_default().name;
+    let info = state.runs.start(generate_request_id(), name);
+    info!("Started run {}", info.run_id);
+    HttpResponse::Ok().json(info)
+}
+
+/// Lists the upstream hosts requests have been sent to, with how many
+/// requests each received and when.
+async fn list_hosts(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "hosts": state.hosts.list() }))
+}
+
+async fn run_summary(run_id: web::Path<String>, state: web::Data<AppState>) -> HttpResponse {
+    let Some(mut tally) = state.runs.get(&run_id) else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Unknown run '{}'", run_id)
+        }));
+    };
+    HttpResponse::Ok().json(RunSummary {
+        succeeded: tally.total - tally.failed,
+        latency: latency_stats(&mut tally.latencies_ms),
+        info: tally.info,
+        total: tally.total,
+        failed: tally.failed,
+        by_status: tally.by_status,
+    })
+}
+
+async fn get_config(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "config": state.config() }))
+}
+
+/// Re-reads the configuration and applies it. Requests already in flight
+/// finish with the values they started with. Changing the cache limits
+/// rebuilds the in-memory cache, dropping its entries.
+async fn reload_config(state: web::Data<AppState>) -> HttpResponse {
+    let new_config = match config::RuntimeConfig::load() {
+        Ok(config) => config,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+    let old_config = std::mem::replace(&mut *state.config.write().unwrap(), new_config.clone());
+
+    if (old_config.cache_max_weight_bytes, old_config.cache_ttl_secs)
+        != (new_config.cache_max_weight_bytes, new_config.cache_ttl_secs)
+    {
+        state.cache.reconfigure(new_config.cache_max_weight_bytes, new_config.cache_ttl());
+    }
+    if (old_config.cache_max_weight_bytes, old_config.negative_cache_ttl_secs)
+        != (new_config.cache_max_weight_bytes, new_config.negative_cache_ttl_secs)
+    {
+        state
+            .negative_cache
+            .reconfigure(new_config.cache_max_weight_bytes, new_config.negative_cache_ttl());
+    }
+    state.dns_cache.set_ttl(new_config.dns_cache_ttl());
+    if new_config.max_ws_connections > old_config.max_ws_connections {
+        state.ws_connections.add_permits(new_config.max_ws_connections - old_config.max_ws_connections);
+    } else if new_config.max_ws_connections < old_config.max_ws_connections {
+        let excess = old_config.max_ws_connections - new_config.max_ws_connections;
+        let forgotten = state.ws_connections.forget_permits(excess);
+        if forgotten < excess {
+            warn!(
+                "Only {} of {} WebSocket slots could be removed; the rest are in use",
+                forgotten, excess
+            );
+        }
+    }
+
+    let old_values = serde_json::to_value(&old_config).unwrap_or_default();
+    let new_values = serde_json::to_value(&new_config).unwrap_or_default();
+    let changed: Vec<&String> = new_values
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(key, value)| old_values.get(key.as_str()) != Some(value))
+        .map(|(key, _)| key)
+        .collect();
+    info!("Configuration reloaded; changed: {:?}", changed);
+    HttpResponse::Ok().json(serde_json::json!({ "config": new_config, "changed": changed }))
+}
+
+async fn flush_dns_cache(state: web::Data<AppState>) -> HttpResponse {
+    let flushed = state.dns_cache.flush();
+    info!("Flushed {} DNS cache entries", flushed);
+    HttpResponse::Ok().json(serde_json::json!({ "flushed": flushed }))
+}
+
+/// Replaces the set of headers injected into every outbound `/proxy` and
+/// `/graphql` request. Headers given on an individual request take precedence.
+async fn set_injected_headers(
+    req: web::Json<HashMap<String, String>>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    for (key, value) in req.iter() {
+        if HeaderName::from_str(key).is_err() || HeaderValue::from_str(value).is_err() {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid header: {}", key)
+            }));
         }
-        tokio::time::sleep(Duration::from_millis(100)).await;
     }
-    let timeout = Duration::from_secs(req.duration.unwrap_or(5));
-    let _ = tokio::time::timeout(timeout, async {
-        while let Some(message) = read.next().await {
-            match message {
-                Ok(msg) => {
-                    if let Ok(text) = msg.to_text() {
-                        messages.push(WebSocketMessage {
-                            direction: "received".to_string(),
-                            content: text.to_string(),
-                            timestamp: Utc::now().to_rfc3339(),
-                        });
-                    }
-                }
-                Err(e) => {
-                    error!("WebSocket receive error: {}", e);
-                    break;
-                }
-            }
+
+    let headers = req.into_inner();
+    info!("Injecting {} header(s) into outbound requests", headers.len());
+    *state.injected_headers.write().unwrap() = headers.clone();
+    HttpResponse::Ok().json(serde_json::json!({ "headers": headers }))
+}
+
+async fn set_interceptors(
+    req: web::Json<Vec<interceptors::Interceptor>>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    for (index, interceptor) in req.iter().enumerate() {
+        if let Err(e) = interceptor.validate() {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid interceptor at index {}: {}", index, e)
+            }));
         }
-    }).await;
+    }
+
+    let interceptors = req.into_inner();
+    info!("Configured {} interceptor(s)", interceptors.len());
+    *state.interceptors.write().unwrap() = interceptors.clone();
+    HttpResponse::Ok().json(serde_json::json!({ "interceptors": interceptors }))
+}
+
+async fn get_interceptors(state: web::Data<AppState>) -> HttpResponse {
+    let interceptors = state.interceptors.read().unwrap().clone();
+    HttpResponse::Ok().json(serde_json::json!({ "interceptors": interceptors }))
+}
+
+async fn set_chaos_config(req: web::Json<chaos::ChaosConfig>, state: web::Data<AppState>) -> HttpResponse {
+    let config = req.into_inner();
+    if let Err(e) = state.chaos.configure(config.clone()) {
+        let mut response = if state.chaos.enabled() {
+            HttpResponse::BadRequest()
+        } else {
+            HttpResponse::Forbidden()
+        };
+        return response.json(serde_json::json!({ "error": e }));
+    }
+    info!("Configured chaos injection with probability {}", config.probability);
+    HttpResponse::Ok().json(serde_json::json!({ "enabled": true, "config": config }))
+}
 
+async fn get_chaos_config(state: web::Data<AppState>) -> HttpResponse {
     HttpResponse::Ok().json(serde_json::json!({
-        "messages": messages,
-        "duration_ms": start_time.elapsed().as_millis(),
-        "status": "completed"
+        "enabled": state.chaos.enabled(),
+        "config": state.chaos.config()
     }))
 }
 
-async fn graphql(req: web::Json<GraphQLRequest>) -> HttpResponse {
+async fn sse_stream(req: web::Json<SseRequest>, state: web::Data<AppState>) -> HttpResponse {
     let start_time = std::time::Instant::now();
 
-    let client = reqwest::Client::new();
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        HeaderName::from_static("content-type"),
-        HeaderValue::from_static("application/json"),
-    );
+    let until_pattern = match req.until_pattern.as_deref().map(Regex::new).transpose() {
+        Ok(pattern) => pattern,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid until_pattern: {}", e)
+            }));
+        }
+    };
 
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, HeaderValue::from_static("text/event-stream"));
+    insert_headers(&mut headers, &state.injected_headers.read().unwrap());
     if let Some(custom_headers) = &req.headers {
-        for (key, value) in custom_headers {
-            if let (Ok(name), Ok(value)) = (HeaderName::from_str(key), HeaderValue::from_str(value)) {
-                headers.insert(name, value);
-            }
-        }
+        insert_headers(&mut headers, custom_headers);
     }
 
-    let body = serde_json::json!({
-        "query": req.query,
-        "variables": req.variables
-    });
-
-    match client.post(&req.url)
+    let duration = Duration::from_secs(req.duration.unwrap_or(5));
+    state.record_host(&req.url);
+    let response = match state.client
+        .get(&req.url)
         .headers(headers)
-        .json(&body)
+        // The shared client's timeout covers the whole body, which would cut
+        // long-lived streams short.
+        .timeout(duration + state.config().request_timeout())
         .send()
-        .await {
-        Ok(response) => {
-            match response.json::<serde_json::Value>().await {
-                Ok(gql_response) => HttpResponse::Ok().json(serde_json::json!({
-                    "data": gql_response.get("data"),
-                    "errors": gql_response.get("errors"),
-                    "duration_ms": start_time.elapsed().as_millis()
-                })),
-                Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-                    "error": format!("Failed to parse GraphQL response: {}", e)
-                }))
-            }
+        .await
+    {
+        Ok(response) => response,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("SSE connection failed: {}", e)
+            }));
         }
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("GraphQL request failed: {}", e)
-        }))
+    };
+
+    let status = response.status().as_u16();
+    let max_events = req.max_events;
+    let (tx, mut rx) = mpsc::channel(SSE_EVENT_BUFFER);
+
+    if req.forward.unwrap_or(false) {
+        tokio::spawn(async move {
+            let outcome = pump_sse(response, duration, until_pattern, max_events, &tx).await;
+            if outcome.backpressure_applied {
+                info!("SSE forwarding to a slow client throttled the upstream read");
+            }
+            let summary = serde_json::json!({
+                "status": status,
+                "events": outcome.events,
+                "stop_reason": outcome.stop_reason,
+                "backpressure_applied": outcome.backpressure_applied,
+                "duration_ms": start_time.elapsed().as_millis() as u64,
+            });
+            let _ = tx
+                .send(sse::SseEvent {
+                    event: "summary".to_string(),
+                    data: summary.to_string(),
+                    id: None,
+                    timestamp: Utc::now().to_rfc3339(),
+                })
+                .await;
+        });
+        let events = futures::stream::unfold(rx, |mut rx| async move {
+            let event = rx.recv().await?;
+            Some((Ok::<_, actix_web::Error>(web::Bytes::from(event.to_wire())), rx))
+        });
+        return HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .insert_header(("Cache-Control", "no-cache"))
+            .streaming(events);
     }
+
+    let mut events = Vec::new();
+    let pump = async {
+        let outcome = pump_sse(response, duration, until_pattern, max_events, &tx).await;
+        drop(tx);
+        outcome
+    };
+    let collect = async {
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+    };
+    let (outcome, ()) = tokio::join!(pump, collect);
+
+    HttpResponse::Ok().json(SseResponse {
+        status,
+        events,
+        stop_reason: outcome.stop_reason,
+        duration_ms: start_time.elapsed().as_millis() as u64,
+    })
+}
+
+struct SsePumpOutcome {
+    stop_reason: String,
+    events: usize,
+    /// Whether the reader ever found `tx` full and had to wait for the
+    /// consumer before reading more from upstream.
+    backpressure_applied: bool,
+}
+
+/// Reads events from an SSE response into `tx` until the duration passes,
+/// a stop condition is met or the receiving side goes away. Upstream is only
+/// read as fast as `tx` drains, so a slow consumer holds back the producer
+/// instead of growing a buffer.
+async fn pump_sse(
+    mut response: reqwest::Response,
+    duration: Duration,
+    until_pattern: Option<Regex>,
+    max_events: Option<usize>,
+    tx: &mpsc::Sender<sse::SseEvent>,
+) -> SsePumpOutcome {
+    let mut parser = sse::SseParser::default();
+    let mut outcome = SsePumpOutcome { stop_reason: "duration".to_string(), events: 0, backpressure_applied: false };
+
+    let _ = tokio::time::timeout(duration, async {
+        loop {
+            match response.chunk().await {
+                Ok(Some(chunk)) => {
+                    for event in parser.feed(&chunk) {
+                        let matched = until_pattern.as_ref().is_some_and(|p| p.is_match(&event.data));
+                        if tx.capacity() == 0 {
+                            outcome.backpressure_applied = true;
+                        }
+                        if tx.send(event).await.is_err() {
+                            outcome.stop_reason = "client_closed".to_string();
+                            return;
+                        }
+                        outcome.events += 1;
+                        if matched {
+                            outcome.stop_reason = "pattern".to_string();
+                            return;
+                        }
+                        if max_events.is_some_and(|max| outcome.events >= max) {
+                            outcome.stop_reason = "max_events".to_string();
+                            return;
+                        }
+                    }
+                }
+                Ok(None) => {
+                    outcome.stop_reason = "closed".to_string();
+                    return;
+                }
+                Err(e) => {
+                    error!("SSE receive error: {}", e);
+                    outcome.stop_reason = "error".to_string();
+                    return;
+                }
+            }
+        }
+    }).await;
+    outcome
 }
 
 #[get("/metrics")]
@@ -360,21 +4505,149 @@ async fn metrics() -> HttpResponse {
         .body(String::from_utf8(buffer).unwrap())
 }
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));    
-    info!("Starting server at http://localhost:8000");
+fn metric_family_to_json(family: &prometheus::proto::MetricFamily) -> serde_json::Value {
+    use prometheus::proto::MetricType;
+
+    let metric_type = match family.get_field_type() {
+        MetricType::COUNTER => "counter",
+        MetricType::GAUGE => "gauge",
+        MetricType::HISTOGRAM => "histogram",
+        MetricType::SUMMARY => "summary",
+        MetricType::UNTYPED => "untyped",
+    };
+
+    let samples: Vec<serde_json::Value> = family
+        .get_metric()
+        .iter()
+        .map(|metric| {
+            let labels: HashMap<&str, &str> = metric
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name(), label.get_value()))
+                .collect();
+            let value = match family.get_field_type() {
+                MetricType::COUNTER => serde_json::json!(metric.get_counter().get_value()),
+                MetricType::GAUGE => serde_json::json!(metric.get_gauge().get_value()),
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    serde_json::json!({
+                        "count": histogram.get_sample_count(),
+                        "sum": histogram.get_sample_sum(),
+                        "buckets": histogram.get_bucket().iter().map(|b| serde_json::json!({
+                            "le": b.get_upper_bound(),
+                            "count": b.get_cumulative_count()
+                        })).collect::<Vec<_>>()
+                    })
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    serde_json::json!({
+                        "count": summary.get_sample_count(),
+                        "sum": summary.get_sample_sum(),
+                        "quantiles": summary.get_quantile().iter().map(|q| serde_json::json!({
+                            "quantile": q.get_quantile(),
+                            "value": q.get_value()
+                        })).collect::<Vec<_>>()
+                    })
+                }
+                MetricType::UNTYPED => serde_json::json!(metric.get_untyped().get_value()),
+            };
+            serde_json::json!({ "labels": labels, "value": value })
+        })
+        .collect();
+
+    serde_json::json!({
+        "name": family.get_name(),
+        "help": family.get_help(),
+        "type": metric_type,
+        "metrics": samples
+    })
+}
+
+#[get("/metrics.json")]
+async fn metrics_json() -> HttpResponse {
+    let families: Vec<serde_json::Value> = prometheus::gather()
+        .iter()
+        .map(metric_family_to_json)
+        .collect();
+    HttpResponse::Ok().json(families)
+}
+
+/// Builds the shared state used by both the server and `--run`.
+async fn build_state(config: config::RuntimeConfig) -> std::io::Result<web::Data<AppState>> {
+    // Timeouts are applied per request from the runtime config so a reload
+    // takes effect without rebuilding the client.
+    let dns_cache = dns::DnsCache::new(config.dns_cache_ttl());
     let client = reqwest::Client::builder()
-        .timeout(REQUEST_TIMEOUT)
+        .dns_resolver(dns_cache.resolver())
         .build()
         .expect("Failed to create HTTP client");
 
-    let cache: Cache<String, ProxyResponse> = Cache::builder()
-        .max_capacity(CACHE_MAX_CAPACITY)
-        .time_to_live(CACHE_TIME_TO_LIVE)
-        .build();
+    let cache = cache::ResponseCache::new(config.cache_max_weight_bytes, config.cache_ttl()).await;
+    let negative_cache =
+        cache::ResponseCache::new(config.cache_max_weight_bytes, config.negative_cache_ttl()).await;
+
+    Ok(web::Data::new(AppState {
+        cache,
+        negative_cache,
+        client,
+        ws_connections: Arc::new(Semaphore::new(config.max_ws_connections)),
+        injected_headers: Arc::new(RwLock::new(HashMap::new())),
+        cancellations: Arc::new(Mutex::new(HashMap::new())),
+        interceptors: Arc::new(RwLock::new(Vec::new())),
+        uploads: Arc::new(uploads::UploadStore::new()?),
+        config: Arc::new(RwLock::new(config)),
+        dns_cache,
+        baselines: Arc::new(baselines::BaselineStore::load()?),
+        chaos: Arc::new(chaos::Chaos::from_env()),
+        runs: Arc::new(runs::RunStore::default()),
+        hosts: Arc::new(hosts::HostStore::default()),
+        jobs: Arc::new(jobs::JobStore::default()),
+    }))
+}
+
+const USAGE: &str = "usage: api-tester [--run <collection-file>]";
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    logging::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let run_file = match args.as_slice() {
+        [] => None,
+        [flag, file] if flag == "--run" => Some(file.clone()),
+        [arg] if arg.starts_with("--run=") => Some(arg["--run=".len()..].to_string()),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let config = config::RuntimeConfig::load()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let state = build_state(config).await?;
+
+    if let Some(file) = run_file {
+        let all_passed = match run_collection(&file, state).await {
+            Ok(all_passed) => all_passed,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            }
+        };
+        std::process::exit(if all_passed { 0 } else { 1 });
+    }
 
-    let state = web::Data::new(AppState { cache, client });
+    info!("Starting server at http://localhost:8000");
+    let uploads = state.uploads.clone();
+    let jobs = state.jobs.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            uploads.sweep();
+            jobs.sweep();
+        }
+    });
     HttpServer::new(move || {
         App::new()
             .app_data(state.clone())
@@ -386,11 +4659,772 @@ async fn main() -> std::io::Result<()> {
                     .max_age(3600)
             )
             .service(metrics)
+            .service(metrics_json)
+            .service(capabilities)
             .route("/proxy", web::post().to(proxy))
+            .route("/batch", web::post().to(batch))
+            .route("/batch/stream", web::post().to(batch_stream))
+            .route("/multiregion", web::post().to(multiregion))
+            .route("/cancel/{token}", web::post().to(cancel))
+            .route("/convert", web::post().to(convert))
+            .route("/upload", web::post().to(upload))
+            .route("/openapi/diff", web::post().to(openapi_diff))
+            .route("/import/openapi", web::post().to(import_openapi))
             .route("/ws", web::post().to(websocket))
+            .route("/ws/bench", web::post().to(websocket_bench))
             .route("/graphql", web::post().to(graphql))
+            .route("/graphql/loadtest", web::post().to(graphql_loadtest))
+            .route("/detect", web::post().to(detect))
+            .route("/tls/bench", web::post().to(tls_bench))
+            .route("/sse", web::post().to(sse_stream))
+            .route("/config/headers", web::post().to(set_injected_headers))
+            .route("/config", web::get().to(get_config))
+            .route("/config/reload", web::post().to(reload_config))
+            .route("/dns-cache", web::delete().to(flush_dns_cache))
+            .route("/baselines/latency/{name}", web::post().to(record_latency_baseline))
+            .route("/interceptors", web::post().to(set_interceptors))
+            .route("/interceptors", web::get().to(get_interceptors))
+            .route("/chaos/config", web::post().to(set_chaos_config))
+            .route("/chaos/config", web::get().to(get_chaos_config))
+            .route("/run/start", web::post().to(start_run))
+            .route("/schedule-once", web::post().to(schedule_once))
+            .route("/jobs/{id}", web::get().to(get_job))
+            .route("/run/{id}/summary", web::get().to(run_summary))
+            .route("/hosts", web::get().to(list_hosts))
     })
     .bind("127.0.0.1:8000")?
     .run()
     .await
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Starts an upstream on a free port that answers every connection with
+    /// `respond(request_head)`, written as a raw HTTP response. Returns its
+    /// base URL.
+    async fn mock_upstream(respond: impl Fn(&str) -> String + Send + Sync + 'static) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let respond = Arc::new(respond);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let respond = respond.clone();
+                tokio::spawn(async move {
+                    let mut head = Vec::new();
+                    let mut buf = [0u8; 4096];
+                    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => head.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let reply = respond(&String::from_utf8_lossy(&head));
+                    let _ = socket.write_all(reply.as_bytes()).await;
+                    let _ = socket.shutdown().await;
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn http_response(status: &str, headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}",
+            status,
+            body.len(),
+            headers,
+            body
+        )
+    }
+
+    /// Echoes the request head back as `{"head": "..."}`, lowercased.
+    fn echo_head(head: &str) -> String {
+        let body = serde_json::json!({ "head": head.to_ascii_lowercase() }).to_string();
+        http_response("200 OK", "", &body)
+    }
+
+    async fn test_state() -> AppState {
+        build_state(config::RuntimeConfig::default()).await.unwrap().get_ref().clone()
+    }
+
+    fn proxy_request(value: serde_json::Value) -> ProxyRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn select_projects_nested_and_indexed_paths() {
+        let body = r#"{"user":{"name":"ada","roles":["admin","dev"]},"items":[{"id":1},{"id":2}]}"#;
+        let url = mock_upstream(move |_| http_response("200 OK", "", body)).await;
+        let req = proxy_request(serde_json::json!({
+            "url": url,
+            "method": "GET",
+            "select": ["user.name", "user.roles[-1]", "$.items[1].id", "missing.key"],
+        }));
+        let response = execute_proxy(&req, &test_state().await).await.unwrap();
+        assert_eq!(
+            response.body,
+            serde_json::json!({
+                "user.name": "ada",
+                "user.roles[-1]": "dev",
+                "$.items[1].id": 2,
+                "missing.key": null,
+            })
+        );
+        assert_eq!(response.original_body_size, Some(body.len()));
+    }
+
+    #[tokio::test]
+    async fn injected_headers_are_sent_and_request_headers_override_them() {
+        let url = mock_upstream(echo_head).await;
+        let state = test_state().await;
+        *state.injected_headers.write().unwrap() = HashMap::from([
+            ("X-Env".to_string(), "staging".to_string()),
+            ("X-Team".to_string(), "platform".to_string()),
+        ]);
+        let req = proxy_request(serde_json::json!({
+            "url": url,
+            "method": "GET",
+            "headers": { "X-Team": "payments" },
+        }));
+        let response = execute_proxy(&req, &state).await.unwrap();
+        let head = response.body["head"].as_str().unwrap();
+        assert!(head.contains("x-env: staging"));
+        assert!(head.contains("x-team: payments"));
+        assert!(!head.contains("x-team: platform"));
+    }
+
+    #[tokio::test]
+    async fn redirect_without_location_is_flagged() {
+        let url = mock_upstream(|_| http_response("302 Found", "", "{}")).await;
+        let req = proxy_request(serde_json::json!({ "url": url, "method": "GET" }));
+        let response = execute_proxy(&req, &test_state().await).await.unwrap();
+        assert_eq!(response.status, 302);
+        assert_eq!(response.error_kind.as_deref(), Some("redirect_no_location"));
+    }
+
+    #[tokio::test]
+    async fn redirect_with_location_is_followed() {
+        let url = mock_upstream(|head| {
+            if head.starts_with("GET /moved ") {
+                http_response("200 OK", "", "{}")
+            } else {
+                http_response("302 Found", "Location: /moved\r\n", "{}")
+            }
+        })
+        .await;
+        let req = proxy_request(serde_json::json!({ "url": url, "method": "GET" }));
+        let response = execute_proxy(&req, &test_state().await).await.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.error_kind, None);
+    }
+
+    async fn proxy_reply(req: serde_json::Value) -> (StatusCode, serde_json::Value) {
+        let state = web::Data::new(test_state().await);
+        let response = proxy(web::Json(proxy_request(req)), state).await;
+        let status = response.status();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn success_statuses_map_to_ok_and_keep_the_real_status() {
+        let url = mock_upstream(|_| http_response("404 Not Found", "", "{}")).await;
+        let (status, body) = proxy_reply(serde_json::json!({
+            "url": url,
+            "method": "GET",
+            "passthrough_status": true,
+            "success_statuses": [404],
+        }))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], 404);
+    }
+
+    #[actix_web::test]
+    async fn passthrough_status_mirrors_other_statuses() {
+        let url = mock_upstream(|_| http_response("404 Not Found", "", "{}")).await;
+        let (status, _) = proxy_reply(serde_json::json!({
+            "url": url.clone(),
+            "method": "GET",
+            "passthrough_status": true,
+            "success_statuses": [410],
+        }))
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, body) = proxy_reply(serde_json::json!({ "url": url, "method": "GET" })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], 404);
+    }
+
+    #[tokio::test]
+    async fn sort_keys_makes_body_hash_independent_of_key_order() {
+        let mut hashes = Vec::new();
+        for body in [r#"{"b":{"y":1,"x":2},"a":1}"#, r#"{"a":1,"b":{"x":2,"y":1}}"#] {
+            let url = mock_upstream(move |_| http_response("200 OK", "", body)).await;
+            let req = proxy_request(serde_json::json!({
+                "url": url,
+                "method": "GET",
+                "sort_keys": true,
+                "hash_body": true,
+            }));
+            hashes.push(execute_proxy(&req, &test_state().await).await.unwrap().body_hash);
+        }
+        assert!(hashes[0].is_some());
+        assert_eq!(hashes[0], hashes[1]);
+    }
+
+    #[tokio::test]
+    async fn transforms_reshape_the_body_but_not_its_hash() {
+        let body = r#"{"user":{"firstName":"ada","token":"t"}}"#;
+        let url = mock_upstream(move |_| http_response("200 OK", "", body)).await;
+        let plain = proxy_request(serde_json::json!({ "url": url, "method": "GET", "hash_body": true }));
+        let state = test_state().await;
+        let expected_hash = execute_proxy(&plain, &state).await.unwrap().body_hash;
+
+        let req = proxy_request(serde_json::json!({
+            "url": url,
+            "method": "GET",
+            "hash_body": true,
+            "transforms": [
+                {"type": "redact", "path": "user.token"},
+                {"type": "flatten"},
+                {"type": "key_case", "case": "kebab"},
+            ],
+        }));
+        let response = execute_proxy(&req, &state).await.unwrap();
+        assert_eq!(
+            response.body,
+            serde_json::json!({"user.first-name": "ada", "user.token": "[REDACTED]"})
+        );
+        assert!(expected_hash.is_some());
+        assert_eq!(response.body_hash, expected_hash);
+        assert_eq!(response.original_body_size, Some(body.len()));
+    }
+
+    #[tokio::test]
+    async fn assertions_see_the_body_before_select_and_fold_into_the_verdict() {
+        let body = r#"{"total":5,"items":[{"amount":2},{"amount":3}]}"#;
+        let url = mock_upstream(move |_| http_response("200 OK", "", body)).await;
+        let state = test_state().await;
+        let mut verdicts = Vec::new();
+        for expected_total in [5, 6] {
+            let req = proxy_request(serde_json::json!({
+                "url": url,
+                "method": "GET",
+                "select": ["total"],
+                "assertions": [
+                    {"left": {"sum": "items[*].amount"}, "op": "eq", "right": {"value": expected_total}},
+                ],
+            }));
+            let response = execute_proxy(&req, &state).await.unwrap();
+            assert_eq!(response.body, serde_json::json!({"total": 5}));
+            verdicts.push(response.assertions_passed);
+        }
+        assert_eq!(verdicts, [Some(true), Some(false)]);
+    }
+
+    /// Returns the value of header `name` (lowercase) from a lowercased head.
+    fn header_in<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+        head.lines().find_map(|line| line.strip_prefix(name)?.strip_prefix(':').map(str::trim))
+    }
+
+    #[tokio::test]
+    async fn generated_request_id_is_reported_as_echoed() {
+        let url = mock_upstream(|head| {
+            let head = head.to_ascii_lowercase();
+            let id = header_in(&head, "x-request-id").unwrap_or_default().to_string();
+            http_response("200 OK", &format!("X-Request-Id: {}\r\n", id), "{}")
+        })
+        .await;
+        let req = proxy_request(serde_json::json!({ "url": url, "method": "GET", "check_request_id": {} }));
+        let response = execute_proxy(&req, &test_state().await).await.unwrap();
+        assert!(response.request_id.as_deref().is_some_and(|id| !id.is_empty()));
+        assert_eq!(response.request_id_echoed, Some(true));
+    }
+
+    #[tokio::test]
+    async fn missing_or_different_echo_is_not_echoed() {
+        let url = mock_upstream(|head| {
+            let body = serde_json::json!({ "path": head.lines().next() }).to_string();
+            if head.contains("echo-wrong") {
+                http_response("200 OK", "X-Trace: something-else\r\n", &body)
+            } else {
+                http_response("200 OK", "", &body)
+            }
+        })
+        .await;
+        for path in ["/silent", "/echo-wrong"] {
+            let req = proxy_request(serde_json::json!({
+                "url": format!("{}{}", url, path),
+                "method": "GET",
+                "check_request_id": { "echo_header": "X-Trace" },
+            }));
+            let response = execute_proxy(&req, &test_state().await).await.unwrap();
+            assert_eq!(response.request_id_echoed, Some(false), "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn caller_supplied_request_id_is_kept_and_checked_on_echo_header() {
+        let url = mock_upstream(|head| {
+            let head = head.to_ascii_lowercase();
+            let id = header_in(&head, "x-correlation-id").unwrap_or_default().to_string();
+            http_response("200 OK", &format!("X-Echo: {}\r\n", id), "{}")
+        })
+        .await;
+        let req = proxy_request(serde_json::json!({
+            "url": url,
+            "method": "GET",
+            "headers": { "X-Correlation-Id": "fixed-id-42" },
+            "check_request_id": { "header": "X-Correlation-Id", "echo_header": "X-Echo" },
+        }));
+        let response = execute_proxy(&req, &test_state().await).await.unwrap();
+        assert_eq!(response.request_id.as_deref(), Some("fixed-id-42"));
+        assert_eq!(response.request_id_echoed, Some(true));
+    }
+
+    #[tokio::test]
+    async fn pagination_shapes_the_combined_body_once() {
+        let url = mock_upstream(|head| {
+            if head.starts_with("GET /page2 ") {
+                http_response("200 OK", "", r#"[{"id":2,"amount":3}]"#)
+            } else {
+                http_response("200 OK", "Link: </page2>; rel=\"next\"\r\n", r#"[{"id":1,"amount":2}]"#)
+            }
+        })
+        .await;
+        let req = proxy_request(serde_json::json!({
+            "url": url,
+            "method": "GET",
+            "follow_link_pagination": true,
+            "select": ["$[1].id", "$[0].id"],
+            "assertions": [
+                {"left": {"sum": "[*].amount"}, "op": "eq", "right": {"value": 5}},
+                {"left": {"count": "$"}, "op": "eq", "right": {"value": 2}},
+            ],
+        }));
+        let response = execute_proxy(&req, &test_state().await).await.unwrap();
+        assert_eq!(response.pagination.as_ref().map(|p| p.pages), Some(2));
+        assert_eq!(response.body, serde_json::json!({"$[1].id": 2, "$[0].id": 1}));
+        assert_eq!(response.assertions_passed, Some(true));
+    }
+
+    #[test]
+    fn next_link_resolves_relative_targets_and_multi_valued_rel() {
+        let base = "https://api.example.com/v1/items?page=1";
+        assert_eq!(
+            next_link(r#"</v1/items?page=2>; rel="next""#, base).as_deref(),
+            Some("https://api.example.com/v1/items?page=2")
+        );
+        assert_eq!(
+            next_link(r#"<items?page=2>; rel=next"#, base).as_deref(),
+            Some("https://api.example.com/v1/items?page=2")
+        );
+        assert_eq!(
+            next_link(r#"<https://cdn.example.com/p/9>; rel="next last""#, base).as_deref(),
+            Some("https://cdn.example.com/p/9")
+        );
+        assert_eq!(
+            next_link(r#"<?page=1>; rel="prev", <?page=3>; title="x"; REL="Next""#, base).as_deref(),
+            Some("https://api.example.com/v1/items?page=3")
+        );
+    }
+
+    #[test]
+    fn next_link_ignores_headers_without_rel_next() {
+        let base = "https://api.example.com/items";
+        assert_eq!(next_link(r#"</items?page=1>; rel="prev", </items?page=9>; rel="last""#, base), None);
+        assert_eq!(next_link(r#"</items?page=2>; rel="nextpage""#, base), None);
+        assert_eq!(next_link("</items?page=2>", base), None);
+        assert_eq!(next_link("", base), None);
+    }
+
+    #[tokio::test]
+    async fn tls_probe_finishing_after_the_response_is_still_reported() {
+        let probe = tokio::spawn(async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            Some(tls::TlsDetails {
+                tls_version: "TLSv1.3".to_string(),
+                cipher_suite: "TLS13_AES_128_GCM_SHA256".to_string(),
+            })
+        });
+        let details = await_tls_probe(Some(probe)).await.unwrap();
+        assert_eq!(details.tls_version, "TLSv1.3");
+        assert!(await_tls_probe(None).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn stalling_mid_body_is_a_timeout() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = socket.read(&mut buf).await;
+            let head = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 100\r\n\r\n";
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(br#"{"items":["#).await.unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+        let req = proxy_request(serde_json::json!({ "url": url, "method": "GET", "timeout_ms": 300 }));
+        match execute_proxy(&req, &test_state().await).await {
+            Err(ProxyError::Timeout) => {}
+            other => panic!("expected a timeout, got {:?}", other.map(|r| r.body)),
+        }
+    }
+
+    #[tokio::test]
+    async fn tag_summaries_count_error_statuses_as_failures() {
+        let url = mock_upstream(|head| {
+            if head.starts_with("GET /broken ") {
+                http_response("500 Internal Server Error", "", "{}")
+            } else {
+                http_response("200 OK", "", "{}")
+            }
+        })
+        .await;
+        let state = test_state().await;
+        let mut results = Vec::new();
+        for (index, path) in ["/ok", "/broken"].into_iter().enumerate() {
+            let req = proxy_request(serde_json::json!({ "url": format!("{}{}", url, path), "method": "GET" }));
+            let response = execute_proxy(&req, &state).await.unwrap();
+            results.push(BatchItemResult { index, response: Some(response), error: None });
+        }
+        results.push(BatchItemResult { index: 2, response: None, error: Some("Request failed".to_string()) });
+        let tags = vec![vec!["smoke".to_string()], vec!["smoke".to_string()], vec!["other".to_string()]];
+
+        let summaries = summarize_by_tag(&results, &tags);
+        let smoke = &summaries["smoke"];
+        assert_eq!((smoke.total, smoke.succeeded, smoke.failed), (2, 1, 1));
+        assert_eq!(smoke.pass_rate, 0.5);
+        assert_eq!(summaries["other"].failed, 1);
+    }
+
+    #[test]
+    fn cache_key_separates_decoded_and_raw_response_requests() {
+        let plain = proxy_request(serde_json::json!({ "url": "http://example.com/a", "method": "GET" }));
+        let decoded = proxy_request(serde_json::json!({
+            "url": "http://example.com/a",
+            "method": "GET",
+            "expect_compressed": false,
+        }));
+        let raw = proxy_request(serde_json::json!({
+            "url": "http://example.com/a",
+            "method": "GET",
+            "raw_response": true,
+        }));
+        let keys = [&plain, &decoded, &raw].map(generate_cache_key);
+        assert_ne!(keys[0], keys[1]);
+        assert_ne!(keys[0], keys[2]);
+        assert_ne!(keys[1], keys[2]);
+    }
+
+    fn server_timing(values: &[&str]) -> serde_json::Value {
+        serde_json::to_value(parse_server_timing(values.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn server_timing_reads_dur_and_desc_across_metrics_and_headers() {
+        assert_eq!(
+            server_timing(&["db;dur=53.2, cache;desc=\"Cache Read\";dur=2", "edge; DESC=hit"]),
+            serde_json::json!([
+                {"name": "db", "duration_ms": 53.2},
+                {"name": "cache", "duration_ms": 2.0, "description": "Cache Read"},
+                {"name": "edge", "description": "hit"},
+            ])
+        );
+    }
+
+    #[test]
+    fn server_timing_keeps_quoted_separators_and_escapes() {
+        assert_eq!(
+            server_timing(&[r#"render;desc="a, b; \"c\"";dur=1"#]),
+            serde_json::json!([{"name": "render", "duration_ms": 1.0, "description": "a, b; \"c\""}])
+        );
+    }
+
+    #[test]
+    fn server_timing_skips_malformed_parts() {
+        assert_eq!(
+            server_timing(&[", ;dur=3, app;dur=fast;weird;total=9, miss"]),
+            serde_json::json!([{"name": "app"}, {"name": "miss"}])
+        );
+        assert_eq!(server_timing(&[""]), serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn oversized_gzip_is_flagged_as_a_decompression_bomb() {
+        let compressed = compress_body("gzip", &vec![b' '; 64 * 1024]).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let compressed_len = compressed.len();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = socket.read(&mut buf).await;
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Encoding: gzip\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n",
+                compressed.len()
+            );
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(&compressed).await.unwrap();
+        });
+        let config = config::RuntimeConfig { max_decompressed_bytes: 1024, ..Default::default() };
+        let state = build_state(config).await.unwrap().get_ref().clone();
+        let req = proxy_request(serde_json::json!({ "url": url, "method": "GET", "raw_body": true }));
+        let response = execute_proxy(&req, &state).await.unwrap();
+
+        assert_eq!(response.error_kind.as_deref(), Some("decompression_bomb"));
+        assert_eq!(response.body, serde_json::Value::Null);
+        let raw = response.raw_body.unwrap();
+        assert_eq!(raw.compressed_size, compressed_len);
+        assert_eq!(raw.uncompressed_size, None);
+        assert!(raw.partial_uncompressed_size.is_some_and(|size| size > 1024));
+    }
+
+    #[tokio::test]
+    async fn content_length_mismatch_covers_short_long_and_bodiless_responses() {
+        let url = mock_upstream(|head| {
+            let path = head.split_whitespace().nth(1).unwrap_or("/");
+            let (status, length, body) = match path {
+                "/exact" => ("200 OK", 2, "{}"),
+                "/short" => ("200 OK", 100, "{\"a\":"),
+                "/long" => ("200 OK", 2, "{}trailing"),
+                "/head" => ("200 OK", 50, ""),
+                "/no-content" => ("204 No Content", 7, ""),
+                _ => ("304 Not Modified", 7, ""),
+            };
+            format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status, length, body
+            )
+        })
+        .await;
+        let state = test_state().await;
+        for (method, path, expected) in [
+            ("GET", "/exact", Some(false)),
+            ("GET", "/short", Some(true)),
+            // hyper stops at the declared length, so the extra bytes go unseen.
+            ("GET", "/long", Some(false)),
+            ("HEAD", "/head", None),
+            ("GET", "/no-content", None),
+            ("GET", "/not-modified", None),
+        ] {
+            let req = proxy_request(serde_json::json!({ "url": format!("{}{}", url, path), "method": method }));
+            let response = execute_proxy(&req, &state).await.unwrap();
+            assert_eq!(response.content_length_mismatch, expected, "{} {}", method, path);
+        }
+    }
+
+    #[tokio::test]
+    async fn non_json_bodies_come_back_as_text_or_base64() {
+        let url = mock_upstream(|head| {
+            if head.starts_with("GET /text ") {
+                http_response("200 OK", "Content-Type: text/plain\r\n", "123")
+            } else {
+                http_response("200 OK", "Content-Type: application/octet-stream\r\n", "\u{1}PNG")
+            }
+        })
+        .await;
+        let state = test_state().await;
+
+        let req = proxy_request(serde_json::json!({ "url": format!("{}/text", url), "method": "GET" }));
+        let text = execute_proxy(&req, &state).await.unwrap();
+        assert_eq!(text.body_text.as_deref(), Some("123"));
+        assert_eq!(text.body_type.as_deref(), Some("text"));
+        assert_eq!(text.body, serde_json::Value::Null);
+
+        let req = proxy_request(serde_json::json!({ "url": format!("{}/binary", url), "method": "GET" }));
+        let binary = execute_proxy(&req, &state).await.unwrap();
+        assert_eq!(binary.body_base64.as_deref(), Some(BASE64.encode("\u{1}PNG").as_str()));
+        assert_eq!(binary.body_type.as_deref(), Some("base64"));
+        assert!(binary.body_text.is_none());
+    }
+
+    #[tokio::test]
+    async fn head_has_no_body_and_options_exposes_allow() {
+        let url = mock_upstream(|head| {
+            if head.starts_with("OPTIONS ") {
+                http_response("204 No Content", "Allow: GET, HEAD, OPTIONS\r\n", "")
+            } else {
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 11\r\nConnection: close\r\n\r\n"
+                    .to_string()
+            }
+        })
+        .await;
+        let state = test_state().await;
+
+        let req = proxy_request(serde_json::json!({ "url": url, "method": "HEAD" }));
+        let head = execute_proxy(&req, &state).await.unwrap();
+        assert_eq!(head.status, 200);
+        assert_eq!(head.body, serde_json::Value::Null);
+        assert!(head.body_type.is_none());
+        assert_eq!(head.headers.get("content-length").map(String::as_str), Some("11"));
+
+        let req = proxy_request(serde_json::json!({ "url": url, "method": "OPTIONS" }));
+        let options = execute_proxy(&req, &state).await.unwrap();
+        assert_eq!(options.status, 204);
+        assert_eq!(options.headers.get("allow").map(String::as_str), Some("GET, HEAD, OPTIONS"));
+    }
+
+    #[test]
+    fn warmup_requests_are_left_out_of_steady_state_latency() {
+        let mut tally = LoadTestTally::default();
+        let ok = Ok(serde_json::json!({ "data": {} }));
+        for (index, latency_ms) in [400, 300, 10, 20, 30].into_iter().enumerate() {
+            tally.add(index < 2, Duration::from_millis(latency_ms), &ok);
+        }
+        assert_eq!(tally.total, 5);
+        assert_eq!(tally.succeeded, 5);
+        let steady = latency_stats(&mut tally.latencies).unwrap();
+        assert_eq!((steady.min_ms, steady.mean_ms, steady.max_ms), (10.0, 20.0, 30.0));
+        let warmup = latency_stats(&mut tally.warmup_latencies).unwrap();
+        assert_eq!((warmup.min_ms, warmup.mean_ms, warmup.max_ms), (300.0, 350.0, 400.0));
+    }
+
+    #[test]
+    fn warmup_covering_every_request_leaves_no_steady_state_stats() {
+        let mut tally = LoadTestTally::default();
+        tally.add(true, Duration::from_millis(5), &Ok(serde_json::json!({})));
+        assert!(latency_stats(&mut tally.latencies).is_none());
+        assert!(latency_stats(&mut tally.warmup_latencies).is_some());
+    }
+
+    #[tokio::test]
+    async fn unencoded_path_rejects_streamed_uploads() {
+        let req = proxy_request(serde_json::json!({
+            "url": "http://127.0.0.1:9/raw path",
+            "method": "POST",
+            "encode_path": false,
+            "body_upload_id": "upload-1",
+        }));
+        match execute_proxy(&req, &test_state().await).await {
+            Err(ProxyError::BadRequest(message)) => assert!(message.contains("encode_path")),
+            other => panic!("expected a bad request, got {:?}", other.map(|r| r.status)),
+        }
+    }
+
+    #[tokio::test]
+    async fn http3_is_refused_with_an_explanation() {
+        let req = proxy_request(serde_json::json!({
+            "url": "https://127.0.0.1:9/",
+            "method": "GET",
+            "http_version": "3",
+        }));
+        match execute_proxy(&req, &test_state().await).await {
+            Err(ProxyError::BadRequest(message)) => assert!(message.contains("not supported yet")),
+            other => panic!("expected a bad request, got {:?}", other.map(|r| r.status)),
+        }
+    }
+
+    /// Starts a cleartext HTTP/2 upstream. Its first `refusals` connections
+    /// wait for the request's HEADERS and answer with GOAWAY (last stream 0,
+    /// INTERNAL_ERROR), so the request is refused unprocessed; later
+    /// connections answer `{}`. Returns the base URL and a connection count.
+    async fn goaway_upstream(refusals: usize) -> (String, Arc<AtomicUsize>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let index = counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    if index < refusals {
+                        refuse_with_goaway(socket).await;
+                    } else {
+                        serve_empty_json(socket).await;
+                    }
+                });
+            }
+        });
+        (format!("http://{}", addr), connections)
+    }
+
+    async fn refuse_with_goaway(mut socket: tokio::net::TcpStream) {
+        let mut received = Vec::new();
+        let mut buf = [0u8; 4096];
+        // Skips the 24-byte client preface, then walks the frames.
+        let saw_headers = |received: &[u8]| {
+            let mut at = 24;
+            while received.len() >= at + 9 {
+                if received[at + 3] == 0x1 {
+                    return true;
+                }
+                at += 9 + u32::from_be_bytes([0, received[at], received[at + 1], received[at + 2]]) as usize;
+            }
+            false
+        };
+        while !saw_headers(&received) {
+            match socket.read(&mut buf).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => received.extend_from_slice(&buf[..n]),
+            }
+        }
+        let settings = [0, 0, 0, 0x4, 0, 0, 0, 0, 0];
+        let goaway = [0, 0, 8, 0x7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x2];
+        let _ = socket.write_all(&[&settings[..], &goaway[..]].concat()).await;
+        while matches!(socket.read(&mut buf).await, Ok(n) if n > 0) {}
+    }
+
+    async fn serve_empty_json(socket: tokio::net::TcpStream) {
+        let Ok(mut connection) = h2::server::handshake(socket).await else {
+            return;
+        };
+        while let Some(Ok((_, mut respond))) = connection.accept().await {
+            let response = http::Response::builder()
+                .header("content-type", "application/json")
+                .body(())
+                .unwrap();
+            if let Ok(mut stream) = respond.send_response(response, false) {
+                let _ = stream.send_data(web::Bytes::from_static(b"{}"), true);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn goaway_with_an_error_is_retried_once_for_idempotent_methods() {
+        let (url, connections) = goaway_upstream(1).await;
+        let req = proxy_request(serde_json::json!({ "url": url, "method": "PUT", "http_version": "2", "body": {} }));
+        let response = execute_proxy(&req, &test_state().await).await.unwrap();
+        assert_eq!(response.status, 200);
+        assert!(response.retried_on_goaway);
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn repeated_goaway_is_not_retried_again() {
+        let (url, connections) = goaway_upstream(usize::MAX).await;
+        let req = proxy_request(serde_json::json!({ "url": url, "method": "GET", "http_version": "2" }));
+        match execute_proxy(&req, &test_state().await).await {
+            Err(ProxyError::RequestFailed(_)) => {}
+            other => panic!("expected the request to fail, got {:?}", other.map(|r| r.status)),
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn goaway_with_an_error_does_not_replay_a_post() {
+        let (url, connections) = goaway_upstream(1).await;
+        let req = proxy_request(serde_json::json!({ "url": url, "method": "POST", "http_version": "2", "body": {} }));
+        match execute_proxy(&req, &test_state().await).await {
+            Err(ProxyError::RequestFailed(_)) => {}
+            other => panic!("expected the request to fail, got {:?}", other.map(|r| r.status)),
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn other_connection_errors_are_not_goaway() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let error = reqwest::Client::new().get(&url).send().await.unwrap_err();
+        assert!(!is_retryable_goaway(&error, &reqwest::Method::GET));
+    }
+}
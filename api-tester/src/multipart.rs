@@ -0,0 +1,155 @@
+//! Splitting `multipart/*` response bodies (mixed, related, form-data, ...)
+//! into their individual parts, and encoding `multipart/form-data` request
+//! bodies.
+
+use std::collections::HashMap;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MultipartPart {
+    pub headers: HashMap<String, String>,
+    pub body: serde_json::Value,
+    /// How `body` is represented: `json`, `text` or `base64`.
+    pub body_encoding: String,
+}
+
+pub fn is_multipart(content_type: &str) -> bool {
+    content_type.trim().to_ascii_lowercase().starts_with("multipart/")
+}
+
+/// Extracts the `boundary` parameter from a multipart content type.
+fn boundary(content_type: &str) -> Option<String> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("boundary") {
+            return None;
+        }
+        let value = value.trim().trim_matches('"');
+        (!value.is_empty()).then(|| value.to_string())
+    })
+}
+
+/// Decodes a body according to its content type: JSON is parsed, text and
+/// XML are kept as a string and anything else is base64 encoded.
+pub fn decode_body(content_type: Option<&str>, bytes: &[u8]) -> (serde_json::Value, &'static str) {
+    let content_type = content_type.unwrap_or("").to_ascii_lowercase();
+    let is_json = content_type.contains("json");
+    if is_json {
+        if let Ok(value) = serde_json::from_slice(bytes) {
+            return (value, "json");
+        }
+    }
+    if content_type.is_empty() || content_type.starts_with("text/") || is_json || content_type.contains("xml") {
+        if let Ok(text) = std::str::from_utf8(bytes) {
+            return (serde_json::Value::String(text.to_string()), "text");
+        }
+    }
+    (serde_json::Value::String(BASE64.encode(bytes)), "base64")
+}
+
+pub async fn parse(content_type: &str, bytes: &[u8]) -> Result<Vec<MultipartPart>, String> {
+    let boundary = boundary(content_type)
+        .ok_or_else(|| "multipart response has no boundary parameter".to_string())?;
+    let data = actix_web::web::Bytes::copy_from_slice(bytes);
+    let stream = futures::stream::once(async move { Ok::<_, std::io::Error>(data) });
+    let mut multipart = multer::Multipart::new(stream, boundary);
+
+    let mut parts = Vec::new();
+    while let Some(field) = multipart.next_field().await.map_err(|e| e.to_string())? {
+        let headers: HashMap<String, String> = field
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_str().unwrap_or("").to_string()))
+            .collect();
+        let part_type = headers.get("content-type").cloned();
+        let bytes = field.bytes().await.map_err(|e| e.to_string())?;
+        let (body, body_encoding) = decode_body(part_type.as_deref(), &bytes);
+        parts.push(MultipartPart {
+            headers,
+            body,
+            body_encoding: body_encoding.to_string(),
+        });
+    }
+    Ok(parts)
+}
+
+/// A file part in a `multipart` request body. The data is given either as
+/// `content` text or as `content_base64`.
+#[derive(Debug, Deserialize)]
+struct FilePart {
+    filename: String,
+    content_type: Option<String>,
+    content: Option<String>,
+    content_base64: Option<String>,
+}
+
+/// The text sent for a scalar form value. Objects, arrays and null have none.
+pub fn field_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
+
+/// Encodes `fields` as a `multipart/form-data` body and returns it with its
+/// content type. Scalars become text fields, an object with a `filename`
+/// becomes a file part and an array repeats the field for each item.
+pub fn encode_form_data(fields: &serde_json::Map<String, Value>) -> Result<(Vec<u8>, String), String> {
+    let boundary = format!("api-tester-{}", hex::encode(rand::random::<[u8; 12]>()));
+    let mut body = Vec::new();
+    for (name, value) in fields {
+        let values = match value {
+            Value::Array(items) => items.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+            if let Some(text) = field_text(value) {
+                body.extend_from_slice(
+                    format!("Content-Disposition: form-data; name=\"{}\"\r\n\r\n", quote(name)).as_bytes(),
+                );
+                body.extend_from_slice(text.as_bytes());
+            } else {
+                let file: FilePart = serde_json::from_value(value.clone())
+                    .map_err(|e| format!("Invalid multipart field '{}': {}", name, e))?;
+                let data = match (file.content, file.content_base64) {
+                    (Some(text), None) => text.into_bytes(),
+                    (None, Some(encoded)) => BASE64
+                        .decode(encoded.trim())
+                        .map_err(|e| format!("Invalid content_base64 in multipart field '{}': {}", name, e))?,
+                    _ => {
+                        return Err(format!(
+                            "Multipart field '{}' needs exactly one of content or content_base64",
+                            name
+                        ))
+                    }
+                };
+                body.extend_from_slice(
+                    format!(
+                        "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+                        quote(name),
+                        quote(&file.filename),
+                        file.content_type.as_deref().unwrap_or("application/octet-stream")
+                    )
+                    .as_bytes(),
+                );
+                body.extend_from_slice(&data);
+            }
+            body.extend_from_slice(b"\r\n");
+        }
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    Ok((body, format!("multipart/form-data; boundary={}", boundary)))
+}
+
+/// Escapes a name for a quoted `Content-Disposition` parameter the way
+/// browsers do.
+fn quote(name: &str) -> String {
+    name.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A")
+}
@@ -0,0 +1,566 @@
+//! Structural diff of two OpenAPI (or Swagger 2) documents, classifying each
+//! change as breaking or non-breaking for existing clients.
+//!
+//! The rules are deliberately conservative: anything that can make a request
+//! that used to succeed fail, or a response a client relied on change shape,
+//! is breaking. `$ref`s are resolved within the same document.
+//!
+//! `get_operations` enumerates the GET operations of a spec with example
+//! parameter values filled in, for `POST /import/openapi`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::Serialize;
+use serde_json::Value;
+
+const METHODS: [&str; 8] = ["get", "put", "post", "delete", "options", "head", "patch", "trace"];
+/// Guards schema comparison against recursive `$ref` cycles.
+const MAX_SCHEMA_DEPTH: usize = 32;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Breaking,
+    NonBreaking,
+}
+
+/// A GET operation ready to request: the path template with parameters
+/// substituted and the query parameters that have values.
+#[derive(Debug, Clone)]
+pub struct GetOperation {
+    pub path: String,
+    pub operation_id: Option<String>,
+    /// The path with `{param}` placeholders replaced, when every path
+    /// parameter had a value.
+    pub resolved_path: Option<String>,
+    pub query: Vec<(String, String)>,
+    /// Required parameters no value was found for.
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Change {
+    pub category: &'static str,
+    pub severity: Severity,
+    pub method: String,
+    pub path: String,
+    pub detail: String,
+}
+
+/// Parses a spec given either as a JSON object or as JSON/YAML text.
+pub fn load(spec: &Value) -> Result<Value, String> {
+    let doc = match spec {
+        Value::String(text) => serde_json::from_str(text)
+            .or_else(|_| serde_yaml::from_str(text))
+            .map_err(|e| format!("not valid JSON or YAML: {}", e))?,
+        other => other.clone(),
+    };
+    if !doc.get("paths").is_some_and(Value::is_object) {
+        return Err("document has no `paths` object".to_string());
+    }
+    Ok(doc)
+}
+
+pub fn diff(old: &Value, new: &Value) -> Vec<Change> {
+    let old_ops = operations(old);
+    let new_ops = operations(new);
+    let mut changes = Vec::new();
+
+    for ((path, method), old_op) in &old_ops {
+        let Some(new_op) = new_ops.get(&(path.clone(), method.clone())) else {
+            changes.push(change("endpoint_removed", Severity::Breaking, method, path, "Endpoint removed".to_string()));
+            continue;
+        };
+        let mut ctx = OpDiff { old, new, method, path, changes: &mut changes };
+        ctx.parameters(old_op, new_op);
+        ctx.request_body(old_op, new_op);
+        ctx.responses(old_op, new_op);
+    }
+    for (path, method) in new_ops.keys() {
+        if !old_ops.contains_key(&(path.clone(), method.clone())) {
+            changes.push(change("endpoint_added", Severity::NonBreaking, method, path, "Endpoint added".to_string()));
+        }
+    }
+    changes
+}
+
+/// The API root a spec declares: the first absolute `servers` URL (OpenAPI 3)
+/// or `schemes`, `host` and `basePath` (Swagger 2).
+pub fn base_url(doc: &Value) -> Option<String> {
+    let server = doc
+        .pointer("/servers/0/url")
+        .and_then(Value::as_str)
+        .filter(|url| url.starts_with("http://") || url.starts_with("https://"));
+    if let Some(server) = server {
+        return Some(server.trim_end_matches('/').to_string());
+    }
+    let host = doc.get("host").and_then(Value::as_str)?;
+    let scheme = doc.pointer("/schemes/0").and_then(Value::as_str).unwrap_or("https");
+    let base_path = doc.get("basePath").and_then(Value::as_str).unwrap_or("");
+    Some(format!("{}://{}{}", scheme, host, base_path.trim_end_matches('/')))
+}
+
+/// Every GET operation in `doc`. Parameter values come from `examples` by
+/// name, then from the spec's `example`, `default` or first `enum` value.
+/// Optional query parameters are only sent when `examples` names them.
+pub fn get_operations(doc: &Value, examples: &HashMap<String, String>) -> Vec<GetOperation> {
+    operations(doc)
+        .into_iter()
+        .filter(|((_, method), _)| method == "get")
+        .map(|((path, _), op)| {
+            let mut resolved_path = path.clone();
+            let mut query = Vec::new();
+            let mut missing = Vec::new();
+            for ((location, name), param) in params(doc, &op) {
+                let value = examples.get(&name).cloned().or_else(|| example_value(doc, &param));
+                match (location.as_str(), value) {
+                    ("path", Some(value)) => resolved_path = resolved_path.replace(&format!("{{{}}}", name), &value),
+                    ("path", None) => missing.push(name),
+                    ("query", Some(value)) if is_required(&param) || examples.contains_key(&name) => {
+                        query.push((name, value))
+                    }
+                    ("query", None) if is_required(&param) => missing.push(name),
+                    _ => {}
+                }
+            }
+            GetOperation {
+                operation_id: op.get("operationId").and_then(Value::as_str).map(str::to_string),
+                resolved_path: missing.is_empty().then_some(resolved_path),
+                path,
+                query,
+                missing,
+            }
+        })
+        .collect()
+}
+
+/// A sample value for a parameter, rendered as it would appear in a URL.
+fn example_value(doc: &Value, param: &Value) -> Option<String> {
+    let schema = param.get("schema").map(|s| resolve(doc, s)).unwrap_or(param);
+    let value = param
+        .get("example")
+        .or_else(|| param.get("x-example"))
+        .or_else(|| schema.get("example"))
+        .or_else(|| schema.get("default"))
+        .or_else(|| schema.pointer("/enum/0"))?;
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Null | Value::Array(_) | Value::Object(_) => None,
+        other => Some(other.to_string()),
+    }
+}
+
+fn change(category: &'static str, severity: Severity, method: &str, path: &str, detail: String) -> Change {
+    Change {
+        category,
+        severity,
+        method: method.to_uppercase(),
+        path: path.to_string(),
+        detail,
+    }
+}
+
+/// Operations keyed by (path, method), each with path-level parameters merged
+/// into its own.
+fn operations(doc: &Value) -> BTreeMap<(String, String), Value> {
+    let mut ops = BTreeMap::new();
+    let Some(paths) = doc.get("paths").and_then(Value::as_object) else {
+        return ops;
+    };
+    for (path, item) in paths {
+        let item = resolve(doc, item);
+        let shared = item.get("parameters").and_then(Value::as_array).cloned().unwrap_or_default();
+        for method in METHODS {
+            if let Some(op) = item.get(method) {
+                let mut op = resolve(doc, op).clone();
+                let mut params = shared.clone();
+                params.extend(op.get("parameters").and_then(Value::as_array).cloned().unwrap_or_default());
+                op["parameters"] = Value::Array(params);
+                ops.insert((path.clone(), method.to_string()), op);
+            }
+        }
+    }
+    ops
+}
+
+/// Follows a local `$ref` (`#/components/schemas/User`). Unresolvable
+/// references are returned unchanged.
+fn resolve<'a>(doc: &'a Value, value: &'a Value) -> &'a Value {
+    let mut current = value;
+    for _ in 0..MAX_SCHEMA_DEPTH {
+        let Some(pointer) = current.get("$ref").and_then(Value::as_str).and_then(|r| r.strip_prefix('#')) else {
+            break;
+        };
+        match doc.pointer(pointer) {
+            Some(target) => current = target,
+            None => break,
+        }
+    }
+    current
+}
+
+struct OpDiff<'a> {
+    old: &'a Value,
+    new: &'a Value,
+    method: &'a str,
+    path: &'a str,
+    changes: &'a mut Vec<Change>,
+}
+
+impl OpDiff<'_> {
+    fn push(&mut self, category: &'static str, severity: Severity, detail: String) {
+        self.changes.push(change(category, severity, self.method, self.path, detail));
+    }
+
+    fn compare(&self, old_schema: &Value, new_schema: &Value, direction: Direction) -> Vec<(Severity, String)> {
+        let mut schema_diff = SchemaDiff { old_doc: self.old, new_doc: self.new, direction, found: Vec::new() };
+        schema_diff.compare(old_schema, new_schema, "body", 0);
+        schema_diff.found
+    }
+
+    fn parameters(&mut self, old_op: &Value, new_op: &Value) {
+        let old_params = params(self.old, old_op);
+        let new_params = params(self.new, new_op);
+
+        for (key, old_param) in &old_params {
+            let label = format!("{} parameter '{}'", key.0, key.1);
+            let Some(new_param) = new_params.get(key) else {
+                self.push("parameter_removed", Severity::Breaking, format!("{} removed", label));
+                continue;
+            };
+            let was_required = is_required(old_param);
+            let now_required = is_required(new_param);
+            if !was_required && now_required {
+                self.push("parameter_changed", Severity::Breaking, format!("{} became required", label));
+            } else if was_required && !now_required {
+                self.push("parameter_changed", Severity::NonBreaking, format!("{} became optional", label));
+            }
+            let old_type = param_type(self.old, old_param);
+            let new_type = param_type(self.new, new_param);
+            if old_type != new_type {
+                self.push(
+                    "parameter_changed",
+                    Severity::Breaking,
+                    format!("{} type changed from {} to {}", label, describe(&old_type), describe(&new_type)),
+                );
+            }
+        }
+        for (key, new_param) in &new_params {
+            if old_params.contains_key(key) {
+                continue;
+            }
+            let (severity, kind) = if is_required(new_param) {
+                (Severity::Breaking, "Required")
+            } else {
+                (Severity::NonBreaking, "Optional")
+            };
+            self.push("parameter_added", severity, format!("{} {} parameter '{}' added", kind, key.0, key.1));
+        }
+    }
+
+    fn request_body(&mut self, old_op: &Value, new_op: &Value) {
+        let old_body = old_op.get("requestBody").map(|b| resolve(self.old, b));
+        let new_body = new_op.get("requestBody").map(|b| resolve(self.new, b));
+        match (old_body, new_body) {
+            (None, Some(body)) if is_required(body) => {
+                self.push("request_body_changed", Severity::Breaking, "Required request body added".to_string())
+            }
+            (None, Some(_)) => {
+                self.push("request_body_changed", Severity::NonBreaking, "Optional request body added".to_string())
+            }
+            (Some(_), None) => {
+                self.push("request_body_changed", Severity::Breaking, "Request body removed".to_string())
+            }
+            (Some(old_body), Some(new_body)) => {
+                if !is_required(old_body) && is_required(new_body) {
+                    self.push("request_body_changed", Severity::Breaking, "Request body became required".to_string());
+                }
+                for (media, old_schema, new_schema) in shared_media(old_body, new_body) {
+                    for (severity, detail) in self.compare(old_schema, new_schema, Direction::Request) {
+                        self.push("request_body_changed", severity, format!("{}: {}", media, detail));
+                    }
+                }
+            }
+            (None, None) => {}
+        }
+    }
+
+    fn responses(&mut self, old_op: &Value, new_op: &Value) {
+        let empty = serde_json::Map::new();
+        let old_responses = old_op.get("responses").and_then(Value::as_object).unwrap_or(&empty);
+        let new_responses = new_op.get("responses").and_then(Value::as_object).unwrap_or(&empty);
+
+        for (status, old_response) in old_responses {
+            let Some(new_response) = new_responses.get(status) else {
+                self.push("response_removed", Severity::Breaking, format!("Response {} removed", status));
+                continue;
+            };
+            let old_response = resolve(self.old, old_response);
+            let new_response = resolve(self.new, new_response);
+            for (media, old_schema, new_schema) in shared_media(old_response, new_response) {
+                for (severity, detail) in self.compare(old_schema, new_schema, Direction::Response) {
+                    self.push("response_schema_changed", severity, format!("{} {}: {}", status, media, detail));
+                }
+            }
+        }
+        for status in new_responses.keys() {
+            if !old_responses.contains_key(status) {
+                self.push("response_added", Severity::NonBreaking, format!("Response {} added", status));
+            }
+        }
+    }
+}
+
+/// Parameters keyed by (location, name).
+fn params(doc: &Value, op: &Value) -> BTreeMap<(String, String), Value> {
+    op.get("parameters")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(|p| resolve(doc, p))
+        .filter_map(|p| {
+            let location = p.get("in")?.as_str()?.to_string();
+            let name = p.get("name")?.as_str()?.to_string();
+            Some(((location, name), p.clone()))
+        })
+        .collect()
+}
+
+fn is_required(value: &Value) -> bool {
+    value.get("required").and_then(Value::as_bool).unwrap_or(false)
+}
+
+/// The parameter's type, from `schema` (OpenAPI 3) or inline (Swagger 2).
+fn param_type(doc: &Value, param: &Value) -> Option<String> {
+    let schema = param.get("schema").map(|s| resolve(doc, s)).unwrap_or(param);
+    schema_type(schema)
+}
+
+fn schema_type(schema: &Value) -> Option<String> {
+    match schema.get("type")? {
+        Value::String(t) => Some(t.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+fn describe(schema_type: &Option<String>) -> &str {
+    schema_type.as_deref().unwrap_or("unspecified")
+}
+
+/// Schemas for each media type both sides declare. Swagger 2 responses carry
+/// a bare `schema`, reported under `*`.
+fn shared_media<'a>(old: &'a Value, new: &'a Value) -> Vec<(String, &'a Value, &'a Value)> {
+    if let (Some(old_schema), Some(new_schema)) = (old.get("schema"), new.get("schema")) {
+        return vec![("*".to_string(), old_schema, new_schema)];
+    }
+    let (Some(old_content), Some(new_content)) = (
+        old.get("content").and_then(Value::as_object),
+        new.get("content").and_then(Value::as_object),
+    ) else {
+        return Vec::new();
+    };
+    old_content
+        .iter()
+        .filter_map(|(media, old_media)| {
+            let new_media = new_content.get(media)?;
+            Some((media.clone(), old_media.get("schema")?, new_media.get("schema")?))
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Request,
+    Response,
+}
+
+/// Compares two schemas property by property. In responses, removing a
+/// property breaks clients that read it; in requests, a newly required
+/// property breaks clients that do not send it.
+struct SchemaDiff<'a> {
+    old_doc: &'a Value,
+    new_doc: &'a Value,
+    direction: Direction,
+    found: Vec<(Severity, String)>,
+}
+
+impl SchemaDiff<'_> {
+    fn compare(&mut self, old: &Value, new: &Value, location: &str, depth: usize) {
+        if depth > MAX_SCHEMA_DEPTH {
+            return;
+        }
+        let old = resolve(self.old_doc, old);
+        let new = resolve(self.new_doc, new);
+
+        let old_type = schema_type(old);
+        let new_type = schema_type(new);
+        if old_type.is_some() && new_type.is_some() && old_type != new_type {
+            self.found.push((
+                Severity::Breaking,
+                format!("{} type changed from {} to {}", location, describe(&old_type), describe(&new_type)),
+            ));
+            return;
+        }
+
+        if let (Some(old_items), Some(new_items)) = (old.get("items"), new.get("items")) {
+            self.compare(old_items, new_items, &format!("{}[]", location), depth + 1);
+        }
+
+        let empty = serde_json::Map::new();
+        let old_props = old.get("properties").and_then(Value::as_object).unwrap_or(&empty);
+        let new_props = new.get("properties").and_then(Value::as_object).unwrap_or(&empty);
+        let old_required = required_set(old);
+        let new_required = required_set(new);
+
+        for (name, old_prop) in old_props {
+            let prop_location = format!("{}.{}", location, name);
+            match new_props.get(name) {
+                Some(new_prop) => self.compare(old_prop, new_prop, &prop_location, depth + 1),
+                None => {
+                    let severity = match self.direction {
+                        Direction::Response => Severity::Breaking,
+                        Direction::Request => Severity::NonBreaking,
+                    };
+                    self.found.push((severity, format!("property {} removed", prop_location)));
+                }
+            }
+        }
+        for name in new_props.keys() {
+            if old_props.contains_key(name) {
+                continue;
+            }
+            let severity = match self.direction {
+                Direction::Request if new_required.contains(name.as_str()) => Severity::Breaking,
+                _ => Severity::NonBreaking,
+            };
+            self.found.push((severity, format!("property {}.{} added", location, name)));
+        }
+        if let Direction::Request = self.direction {
+            for name in new_required.difference(&old_required) {
+                if old_props.contains_key(*name) {
+                    self.found.push((Severity::Breaking, format!("property {}.{} became required", location, name)));
+                }
+            }
+        }
+    }
+}
+
+fn required_set(schema: &Value) -> BTreeSet<&str> {
+    schema
+        .get("required")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn summary(changes: &[Change]) -> Vec<(&'static str, Severity, String, String)> {
+        changes
+            .iter()
+            .map(|c| (c.category, c.severity, format!("{} {}", c.method, c.path), c.detail.clone()))
+            .collect()
+    }
+
+    fn spec(paths: Value) -> Value {
+        json!({"openapi": "3.0.0", "paths": paths, "components": {"schemas": {
+            "User": {"type": "object", "properties": {"id": {"type": "integer"}, "name": {"type": "string"}}},
+        }}})
+    }
+
+    #[test]
+    fn added_and_removed_operations() {
+        let old = spec(json!({
+            "/users": {"get": {"responses": {}}, "delete": {"responses": {}}},
+        }));
+        let new = spec(json!({
+            "/users": {"get": {"responses": {}}},
+            "/teams": {"get": {"responses": {}}},
+        }));
+        assert_eq!(
+            summary(&diff(&old, &new)),
+            vec![
+                ("endpoint_removed", Severity::Breaking, "DELETE /users".to_string(), "Endpoint removed".to_string()),
+                ("endpoint_added", Severity::NonBreaking, "GET /teams".to_string(), "Endpoint added".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn changed_operations_report_each_difference() {
+        let user = json!({"content": {"application/json": {"schema": {"$ref": "#/components/schemas/User"}}}});
+        let old = spec(json!({"/users/{id}": {
+            "parameters": [{"in": "path", "name": "id", "required": true, "schema": {"type": "integer"}}],
+            "get": {
+                "parameters": [{"in": "query", "name": "fields", "schema": {"type": "string"}}],
+                "responses": {"200": user, "404": {}},
+            },
+        }}));
+        let mut new = spec(json!({"/users/{id}": {
+            "parameters": [{"in": "path", "name": "id", "required": true, "schema": {"type": "string"}}],
+            "get": {
+                "parameters": [
+                    {"in": "query", "name": "fields", "required": true, "schema": {"type": "string"}},
+                    {"in": "query", "name": "expand", "schema": {"type": "boolean"}},
+                ],
+                "responses": {"200": user, "410": {}},
+            },
+        }}));
+        new["components"]["schemas"]["User"]["properties"].as_object_mut().unwrap().remove("name");
+
+        let changes = diff(&old, &new);
+        let details: Vec<(&str, Severity, &str)> =
+            changes.iter().map(|c| (c.category, c.severity, c.detail.as_str())).collect();
+        assert!(changes.iter().all(|c| c.method == "GET" && c.path == "/users/{id}"));
+        for expected in [
+            ("parameter_changed", Severity::Breaking, "path parameter 'id' type changed from integer to string"),
+            ("parameter_changed", Severity::Breaking, "query parameter 'fields' became required"),
+            ("parameter_added", Severity::NonBreaking, "Optional query parameter 'expand' added"),
+            ("response_schema_changed", Severity::Breaking, "200 application/json: property body.name removed"),
+            ("response_removed", Severity::Breaking, "Response 404 removed"),
+            ("response_added", Severity::NonBreaking, "Response 410 added"),
+        ] {
+            assert!(details.contains(&expected), "missing {:?} in {:?}", expected, details);
+        }
+        assert_eq!(details.len(), 6, "{:?}", details);
+    }
+
+    #[test]
+    fn request_bodies_break_on_new_required_properties_only() {
+        let body = |schema: Value| {
+            json!({
+                "requestBody": {"required": true, "content": {"application/json": {"schema": schema}}},
+                "responses": {},
+            })
+        };
+        let old = spec(json!({"/users": {"post": body(json!({
+            "type": "object",
+            "properties": {"name": {"type": "string"}},
+        }))}}));
+        let new = spec(json!({"/users": {"post": body(json!({
+            "type": "object",
+            "required": ["email"],
+            "properties": {"email": {"type": "string"}, "nick": {"type": "string"}},
+        }))}}));
+        let details: Vec<(Severity, String)> = diff(&old, &new).into_iter().map(|c| (c.severity, c.detail)).collect();
+        assert_eq!(
+            details,
+            vec![
+                (Severity::NonBreaking, "application/json: property body.name removed".to_string()),
+                (Severity::Breaking, "application/json: property body.email added".to_string()),
+                (Severity::NonBreaking, "application/json: property body.nick added".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn identical_specs_have_no_changes() {
+        let doc = spec(json!({"/users": {"get": {"responses": {"200": {}}}}}));
+        assert!(diff(&doc, &doc).is_empty());
+    }
+}
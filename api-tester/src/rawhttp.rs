@@ -0,0 +1,238 @@
+//! A minimal HTTP/1.1 sender for requests whose path must reach the server
+//! byte for byte.
+//!
+//! reqwest parses every URL with `url::Url`, which resolves dot segments and
+//! percent-encodes reserved characters. That is usually what we want, but it
+//! makes it impossible to test how a server treats `/a/../b` or `%2F` in a
+//! path. This module writes the request line itself over a fresh connection
+//! (`Connection: close`) and hands the result back as a `reqwest::Response`
+//! so the rest of the proxy pipeline is unchanged.
+//!
+//! It is also used for `Expect: 100-continue`, which hyper sends as an
+//! ordinary header without waiting for the interim response.
+
+use std::time::Duration;
+
+use reqwest::header::{CONNECTION, CONTENT_LENGTH, EXPECT, HOST, TRANSFER_ENCODING};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use url::Url;
+
+use crate::tls;
+
+const MAX_RESPONSE_HEADERS: usize = 128;
+/// How long to wait for `100 Continue` before sending the body anyway, as
+/// RFC 9110 allows and curl does.
+const CONTINUE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What the server did after an `Expect: 100-continue` request head.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContinueOutcome {
+    /// The server sent `100 Continue` and the body followed.
+    Continued,
+    /// The server answered with a final status; the body was never sent.
+    Rejected,
+    /// No interim response arrived in time, so the body was sent anyway.
+    TimedOut,
+}
+
+/// Returns the path and query of `raw_url` exactly as written, without the
+/// fragment. Falls back to `/` when the URL has no path.
+pub fn request_target(raw_url: &str) -> String {
+    let after_scheme = raw_url.split_once("://").map_or(raw_url, |(_, rest)| rest);
+    let target = after_scheme
+        .find(['/', '?'])
+        .map_or("", |pos| &after_scheme[pos..]);
+    let target = target.split('#').next().unwrap_or("");
+    match target.chars().next() {
+        None => "/".to_string(),
+        Some('?') => format!("/{}", target),
+        Some(_) => target.to_string(),
+    }
+}
+
+/// Sends `request` with `target` as the literal request-target. With
+/// `expect_continue`, the head carries `Expect: 100-continue` and the body is
+/// held back until the server agrees to take it.
+pub async fn send(
+    request: reqwest::Request,
+    target: &str,
+    expect_continue: bool,
+) -> Result<(reqwest::Response, Option<ContinueOutcome>), String> {
+    let url: &Url = request.url();
+    let host = url.host_str().ok_or("URL has no host")?.to_string();
+    let port = url.port_or_known_default().ok_or("URL has no port")?;
+    let head = encode_head(&request, target, &host, url.port(), expect_continue);
+    let body = request
+        .body()
+        .and_then(|b| b.as_bytes())
+        .map(|b| b.to_vec())
+        .unwrap_or_default();
+
+    let (raw, outcome) = match url.scheme() {
+        "http" => {
+            let stream = TcpStream::connect((host.as_str(), port))
+                .await
+                .map_err(|e| format!("TCP connect failed: {}", e))?;
+            exchange(stream, &head, &body, expect_continue).await?
+        }
+        "https" => exchange(tls::connect(&host, port).await?, &head, &body, expect_continue).await?,
+        other => return Err(format!("Unsupported scheme for raw request: {}", other)),
+    };
+    Ok((parse_response(&raw)?, outcome))
+}
+
+fn encode_head(
+    request: &reqwest::Request,
+    target: &str,
+    host: &str,
+    port: Option<u16>,
+    expect_continue: bool,
+) -> Vec<u8> {
+    let mut head = format!("{} {} HTTP/1.1\r\n", request.method(), target).into_bytes();
+    let headers = request.headers();
+    if !headers.contains_key(HOST) {
+        let host = match port {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+        head.extend_from_slice(format!("host: {}\r\n", host).as_bytes());
+    }
+    for (name, value) in headers {
+        if name == CONNECTION || name == CONTENT_LENGTH || (expect_continue && name == EXPECT) {
+            continue;
+        }
+        head.extend_from_slice(name.as_str().as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value.as_bytes());
+        head.extend_from_slice(b"\r\n");
+    }
+    let body_len = request.body().and_then(|b| b.as_bytes()).map_or(0, |b| b.len());
+    if body_len > 0 {
+        head.extend_from_slice(format!("content-length: {}\r\n", body_len).as_bytes());
+    }
+    if expect_continue {
+        head.extend_from_slice(b"expect: 100-continue\r\n");
+    }
+    head.extend_from_slice(b"connection: close\r\n\r\n");
+    head
+}
+
+async fn exchange<S>(
+    mut stream: S,
+    head: &[u8],
+    body: &[u8],
+    expect_continue: bool,
+) -> Result<(Vec<u8>, Option<ContinueOutcome>), String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(head).await.map_err(|e| e.to_string())?;
+    let mut raw = Vec::new();
+    let mut outcome = None;
+    if expect_continue {
+        stream.flush().await.map_err(|e| e.to_string())?;
+        outcome = Some(match tokio::time::timeout(CONTINUE_TIMEOUT, read_head(&mut stream, &mut raw)).await {
+            Ok(Ok(head_len)) if is_continue(&raw[..head_len]) => {
+                raw.drain(..head_len);
+                ContinueOutcome::Continued
+            }
+            Ok(Ok(_)) => ContinueOutcome::Rejected,
+            Ok(Err(e)) => return Err(e),
+            Err(_) => ContinueOutcome::TimedOut,
+        });
+    }
+    if outcome != Some(ContinueOutcome::Rejected) {
+        stream.write_all(body).await.map_err(|e| e.to_string())?;
+        stream.flush().await.map_err(|e| e.to_string())?;
+    }
+    stream.read_to_end(&mut raw).await.map_err(|e| e.to_string())?;
+    Ok((raw, outcome))
+}
+
+/// Reads into `buf` until it holds a complete response head, returning the
+/// head's length. Bytes past the head stay in `buf`.
+async fn read_head<S>(stream: &mut S, buf: &mut Vec<u8>) -> Result<usize, String>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut chunk = [0u8; 4096];
+    loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            return Ok(pos + 4);
+        }
+        let n = stream.read(&mut chunk).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Err("Connection closed before the response headers ended".to_string());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+fn is_continue(head: &[u8]) -> bool {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_RESPONSE_HEADERS];
+    let mut parsed = httparse::Response::new(&mut headers);
+    matches!(parsed.parse(head), Ok(httparse::Status::Complete(_))) && parsed.code == Some(100)
+}
+
+fn parse_response(raw: &[u8]) -> Result<reqwest::Response, String> {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_RESPONSE_HEADERS];
+    let mut parsed = httparse::Response::new(&mut headers);
+    let head_len = match parsed.parse(raw).map_err(|e| format!("Invalid response: {}", e))? {
+        httparse::Status::Complete(len) => len,
+        httparse::Status::Partial => return Err("Connection closed before the response headers ended".to_string()),
+    };
+
+    let mut builder = http::Response::builder().status(parsed.code.unwrap_or(500));
+    let mut chunked = false;
+    let mut content_length: Option<usize> = None;
+    for header in parsed.headers.iter() {
+        if header.name.eq_ignore_ascii_case(TRANSFER_ENCODING.as_str()) {
+            chunked = String::from_utf8_lossy(header.value).to_ascii_lowercase().contains("chunked");
+            continue;
+        }
+        if header.name.eq_ignore_ascii_case(CONTENT_LENGTH.as_str()) {
+            content_length = std::str::from_utf8(header.value).ok().and_then(|v| v.trim().parse().ok());
+        }
+        builder = builder.header(header.name, header.value);
+    }
+
+    let rest = &raw[head_len..];
+    let body = if chunked {
+        decode_chunked(rest)?
+    } else {
+        match content_length {
+            Some(len) => rest[..len.min(rest.len())].to_vec(),
+            None => rest.to_vec(),
+        }
+    };
+    builder
+        .body(body)
+        .map(reqwest::Response::from)
+        .map_err(|e| e.to_string())
+}
+
+fn decode_chunked(mut data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = data
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or("Truncated chunked body")?;
+        let size_line = String::from_utf8_lossy(&data[..line_end]);
+        let size_hex = size_line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size_hex, 16)
+            .map_err(|_| format!("Invalid chunk size '{}'", size_hex))?;
+        data = &data[line_end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        if data.len() < size {
+            return Err("Truncated chunked body".to_string());
+        }
+        body.extend_from_slice(&data[..size]);
+        data = data.get(size + 2..).unwrap_or(&[]);
+    }
+}
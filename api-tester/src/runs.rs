@@ -0,0 +1,75 @@
+//! Named test runs for comparing separate sessions on one server.
+//!
+//! `POST /run/start` registers a run and returns its id. Requests that carry
+//! the id as `run_id` are counted here and in the `run_*` metrics, and
+//! `GET /run/{id}/summary` reports the totals. Runs live in memory only.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RunInfo {
+    pub run_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub started_at: String,
+}
+
+/// Outcomes recorded for a run. `failed` counts requests that never got a
+/// response; upstream error statuses are completed requests and show up in
+/// `by_status`.
+#[derive(Debug, Clone)]
+pub struct RunTally {
+    pub info: RunInfo,
+    pub total: usize,
+    pub failed: usize,
+    pub by_status: BTreeMap<u16, usize>,
+    pub latencies_ms: Vec<f64>,
+}
+
+#[derive(Default)]
+pub struct RunStore {
+    runs: Mutex<HashMap<String, RunTally>>,
+}
+
+impl RunStore {
+    pub fn start(&self, run_id: String, name: Option<String>) -> RunInfo {
+        let info = RunInfo { run_id: run_id.clone(), name, started_at: chrono::Utc::now().to_rfc3339() };
+        let tally = RunTally {
+            info: info.clone(),
+            total: 0,
+            failed: 0,
+            by_status: BTreeMap::new(),
+            latencies_ms: Vec::new(),
+        };
+        self.runs.lock().unwrap().insert(run_id, tally);
+        info
+    }
+
+    pub fn contains(&self, run_id: &str) -> bool {
+        self.runs.lock().unwrap().contains_key(run_id)
+    }
+
+    /// Records one request: its status and duration, or `None` if it failed
+    /// without a response.
+    pub fn record(&self, run_id: &str, outcome: Option<(u16, f64)>) {
+        let mut runs = self.runs.lock().unwrap();
+        let Some(tally) = runs.get_mut(run_id) else {
+            return;
+        };
+        tally.total += 1;
+        match outcome {
+            Some((status, duration_ms)) => {
+                *tally.by_status.entry(status).or_default() += 1;
+                tally.latencies_ms.push(duration_ms);
+            }
+            None => tally.failed += 1,
+        }
+    }
+
+    pub fn get(&self, run_id: &str) -> Option<RunTally> {
+        self.runs.lock().unwrap().get(run_id).cloned()
+    }
+}
@@ -0,0 +1,93 @@
+//! Incremental parser for the `text/event-stream` wire format.
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SseEvent {
+    pub event: String,
+    pub data: String,
+    pub id: Option<String>,
+    pub timestamp: String,
+}
+
+impl SseEvent {
+    /// Encodes the event in the `text/event-stream` wire format.
+    pub fn to_wire(&self) -> String {
+        let mut wire = String::new();
+        if self.event != "message" {
+            wire.push_str(&format!("event: {}\n", self.event));
+        }
+        if let Some(id) = &self.id {
+            wire.push_str(&format!("id: {}\n", id));
+        }
+        for line in self.data.split('\n') {
+            wire.push_str(&format!("data: {}\n", line));
+        }
+        wire.push('\n');
+        wire
+    }
+}
+
+/// Accumulates raw bytes from the stream and yields complete events. Lines may
+/// be split across chunks, so partial input is buffered until its terminator
+/// arrives.
+#[derive(Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+    last_id: Option<String>,
+}
+
+impl SseParser {
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if let Some(event) = self.process_line(line) {
+                events.push(event);
+            }
+        }
+
+        events
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => self.data.push(value.to_string()),
+            "id" if !value.contains('\0') => self.last_id = Some(value.to_string()),
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+        let data = std::mem::take(&mut self.data).join("\n");
+        Some(SseEvent {
+            event: event.unwrap_or_else(|| "message".to_string()),
+            data,
+            id: self.last_id.clone(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+}
@@ -0,0 +1,169 @@
+//! Side-channel TLS handshakes used to report negotiated connection parameters.
+//!
+//! reqwest does not expose the protocol version or cipher suite it negotiated,
+//! so we perform a separate rustls handshake against the same host and report
+//! what that connection agreed on. The probe mirrors the request's TLS floor
+//! and ALPN offer where it can, but rustls only speaks TLS 1.2 and 1.3, so a
+//! server that offers nothing newer than TLS 1.1 shows up as a failed probe.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::client::Resumption;
+use tokio_rustls::rustls::{
+    version, ClientConfig, OwnedTrustAnchor, ProtocolVersion, RootCertStore, ServerName, SupportedProtocolVersion,
+};
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+use url::Url;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref ROOTS: RootCertStore = {
+        let mut roots = RootCertStore::empty();
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        }));
+        roots
+    };
+    static ref CLIENT_CONFIG: Arc<ClientConfig> = Arc::new(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(ROOTS.clone())
+            .with_no_client_auth(),
+    );
+    /// Like `CLIENT_CONFIG`, but never resumes a session, so every handshake
+    /// is a full one.
+    static ref COLD_CLIENT_CONFIG: Arc<ClientConfig> = {
+        let mut config = ClientConfig::clone(&CLIENT_CONFIG);
+        config.resumption = Resumption::disabled();
+        Arc::new(config)
+    };
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TlsDetails {
+    pub tls_version: String,
+    pub cipher_suite: String,
+}
+
+/// How long each phase of a cold connection took.
+#[derive(Debug, Clone, Copy)]
+pub struct HandshakeTiming {
+    pub connect: Duration,
+    pub handshake: Duration,
+}
+
+/// Opens a TLS connection to `host:port`, verified against the webpki roots.
+pub async fn connect(host: &str, port: u16) -> Result<TlsStream<TcpStream>, String> {
+    timed_connect(&CLIENT_CONFIG, host, port).await.map(|(stream, _)| stream)
+}
+
+/// Opens a fresh TCP connection and performs a full TLS handshake on it,
+/// without session resumption, then drops the connection.
+pub async fn cold_handshake(host: &str, port: u16) -> Result<HandshakeTiming, String> {
+    timed_connect(&COLD_CLIENT_CONFIG, host, port).await.map(|(_, timing)| timing)
+}
+
+async fn timed_connect(
+    config: &Arc<ClientConfig>,
+    host: &str,
+    port: u16,
+) -> Result<(TlsStream<TcpStream>, HandshakeTiming), String> {
+    let server_name = ServerName::try_from(host).map_err(|e| e.to_string())?;
+    let connect = async {
+        let start = Instant::now();
+        let stream = TcpStream::connect((host, port))
+            .await
+            .map_err(|e| format!("TCP connect failed: {}", e))?;
+        let connected = start.elapsed();
+        let stream = TlsConnector::from(config.clone())
+            .connect(server_name, stream)
+            .await
+            .map_err(|e| format!("TLS handshake failed: {}", e))?;
+        Ok((stream, HandshakeTiming { connect: connected, handshake: start.elapsed() - connected }))
+    };
+    tokio::time::timeout(PROBE_TIMEOUT, connect)
+        .await
+        .map_err(|_| "TLS handshake timed out".to_string())?
+}
+
+/// What an established connection negotiated.
+fn details(stream: &TlsStream<TcpStream>) -> Result<TlsDetails, String> {
+    let (_, connection) = stream.get_ref();
+    Ok(TlsDetails {
+        tls_version: connection
+            .protocol_version()
+            .map(format_version)
+            .unwrap_or_default(),
+        cipher_suite: connection
+            .negotiated_cipher_suite()
+            .map(|suite| format!("{:?}", suite.suite()))
+            .unwrap_or_default(),
+    })
+}
+
+/// A probe config for a request's `min_tls_version` and `http_version`. A
+/// `1.3` floor is enforced; lower floors leave rustls at 1.2 and 1.3. The
+/// ALPN offer matches what the request's client would send.
+fn probe_config(min_tls_version: Option<&str>, http_version: Option<&str>) -> Result<ClientConfig, String> {
+    let versions: &[&SupportedProtocolVersion] = match min_tls_version {
+        Some("1.3") => &[&version::TLS13],
+        _ => &[&version::TLS12, &version::TLS13],
+    };
+    let mut config = ClientConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(versions)
+        .map_err(|e| e.to_string())?
+        .with_root_certificates(ROOTS.clone())
+        .with_no_client_auth();
+    config.alpn_protocols = match http_version {
+        Some("1.1") => vec![b"http/1.1".to_vec()],
+        Some("2") => vec![b"h2".to_vec()],
+        _ => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+    };
+    Ok(config)
+}
+
+/// Probes the host behind an `https` URL with the request's TLS floor and
+/// HTTP version. Returns `None` for plain HTTP URLs or when the handshake
+/// fails.
+pub async fn probe_url(url: &str, min_tls_version: Option<&str>, http_version: Option<&str>) -> Option<TlsDetails> {
+    let url = Url::parse(url).ok()?;
+    if url.scheme() != "https" {
+        return None;
+    }
+    let host = url.host_str()?;
+    let port = url.port_or_known_default()?;
+    let probe = async {
+        let config = Arc::new(probe_config(min_tls_version, http_version)?);
+        let (stream, _) = timed_connect(&config, host, port).await?;
+        details(&stream)
+    };
+    match probe.await {
+        Ok(details) => Some(details),
+        Err(e) => {
+            log::warn!("TLS probe of {} failed: {}", host, e);
+            None
+        }
+    }
+}
+
+fn format_version(version: ProtocolVersion) -> String {
+    match version {
+        ProtocolVersion::TLSv1_0 => "TLSv1.0".to_string(),
+        ProtocolVersion::TLSv1_1 => "TLSv1.1".to_string(),
+        ProtocolVersion::TLSv1_2 => "TLSv1.2".to_string(),
+        ProtocolVersion::TLSv1_3 => "TLSv1.3".to_string(),
+        other => format!("{:?}", other),
+    }
+}
@@ -0,0 +1,357 @@
+//! An ordered pipeline of transforms applied to a parsed response body.
+//!
+//! Each transform takes the output of the one before it, so order matters:
+//! `select` followed by `flatten` flattens only the selected values, while
+//! `flatten` followed by `select` has to address the flattened keys. The
+//! pipeline runs after the standalone `sort_keys` and `select` options, and
+//! only affects what is returned; `body_hash` and the cache still see the body
+//! as received.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::interceptors::default_redaction;
+use crate::jsonpath;
+
+/// The `type` tags accepted in a `transforms` list.
+pub const KINDS: [&str; 6] = ["select", "sort_keys", "redact", "flatten", "key_case", "truncate"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Transform {
+    /// Replaces the body with an object of the values at `paths`, keyed by
+    /// path, with `null` for paths that match nothing.
+    Select { paths: Vec<String> },
+    SortKeys,
+    /// Replaces every value matched by `path`.
+    Redact {
+        path: String,
+        #[serde(default = "default_redaction")]
+        replacement: Value,
+    },
+    /// Collapses nested objects and arrays into a single object whose keys
+    /// are the joined paths, e.g. `{"a": [{"b": 1}]}` becomes `{"a.0.b": 1}`.
+    Flatten {
+        #[serde(default = "default_separator")]
+        separator: String,
+    },
+    /// Renames every object key to the given case.
+    KeyCase { case: KeyCase },
+    /// Shortens long strings and arrays anywhere in the body.
+    Truncate {
+        max_string_length: Option<usize>,
+        max_array_items: Option<usize>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyCase {
+    Snake,
+    Camel,
+    Kebab,
+}
+
+fn default_separator() -> String {
+    ".".to_string()
+}
+
+impl Transform {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Transform::Select { paths } => {
+                for path in paths {
+                    jsonpath::parse(path).map_err(|e| format!("Invalid select path: {}", e))?;
+                }
+            }
+            Transform::Redact { path, .. } => {
+                jsonpath::parse(path).map_err(|e| format!("Invalid redact path: {}", e))?;
+            }
+            Transform::Flatten { separator } if separator.is_empty() => {
+                return Err("flatten separator must not be empty".to_string());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn apply(&self, mut body: Value) -> Value {
+        match self {
+            Transform::Select { paths } => {
+                let projected: Map<String, Value> = paths
+                    .iter()
+                    .map(|path| {
+                        let value = jsonpath::query(&body, path).ok().flatten();
+                        (path.clone(), value.unwrap_or(Value::Null))
+                    })
+                    .collect();
+                Value::Object(projected)
+            }
+            Transform::SortKeys => sort_keys(body),
+            Transform::Redact { path, replacement } => {
+                if let Ok(segments) = jsonpath::parse(path) {
+                    jsonpath::replace_all(&mut body, &segments, replacement);
+                }
+                body
+            }
+            Transform::Flatten { separator } => {
+                let mut flat = Map::new();
+                flatten_into(&mut flat, None, body, separator);
+                Value::Object(flat)
+            }
+            Transform::KeyCase { case } => rename_keys(body, *case),
+            Transform::Truncate { max_string_length, max_array_items } => {
+                truncate(body, *max_string_length, *max_array_items)
+            }
+        }
+    }
+}
+
+/// Runs the pipeline over `body`, in order.
+pub fn apply_all(transforms: &[Transform], body: Value) -> Value {
+    transforms.iter().fold(body, |body, transform| transform.apply(body))
+}
+
+/// Recursively rebuilds every object with its keys in sorted order. serde_json's
+/// default map already iterates in key order; rebuilding keeps the guarantee
+/// even if another dependency switches on its `preserve_order` feature.
+pub fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Value::Object(entries.into_iter().map(|(k, v)| (k, sort_keys(v))).collect())
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(sort_keys).collect()),
+        other => other,
+    }
+}
+
+/// Empty objects and arrays are kept as leaves so they do not vanish.
+fn flatten_into(flat: &mut Map<String, Value>, prefix: Option<String>, value: Value, separator: &str) {
+    let join = |key: String| match &prefix {
+        Some(prefix) => format!("{}{}{}", prefix, separator, key),
+        None => key,
+    };
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, child) in map {
+                flatten_into(flat, Some(join(key)), child, separator);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (index, child) in items.into_iter().enumerate() {
+                flatten_into(flat, Some(join(index.to_string())), child, separator);
+            }
+        }
+        // A scalar body has no key of its own.
+        leaf => {
+            flat.insert(prefix.unwrap_or_default(), leaf);
+        }
+    }
+}
+
+fn rename_keys(value: Value, case: KeyCase) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, child)| (convert_case(&key, case), rename_keys(child, case)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(|child| rename_keys(child, case)).collect()),
+        other => other,
+    }
+}
+
+/// Splits a key into lowercase words on `_`, `-`, spaces and lower-to-upper
+/// transitions, then joins them in the requested style.
+fn convert_case(key: &str, case: KeyCase) -> String {
+    let mut words: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut prev_lower = false;
+    for c in key.chars() {
+        if c == '_' || c == '-' || c == ' ' {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            prev_lower = false;
+            continue;
+        }
+        if c.is_uppercase() && prev_lower && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        prev_lower = c.is_lowercase() || c.is_ascii_digit();
+        current.extend(c.to_lowercase());
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+
+    match case {
+        KeyCase::Snake => words.join("_"),
+        KeyCase::Kebab => words.join("-"),
+        KeyCase::Camel => words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) if i > 0 => first.to_uppercase().chain(chars).collect(),
+                    _ => word.clone(),
+                }
+            })
+            .collect(),
+    }
+}
+
+fn truncate(value: Value, max_string_length: Option<usize>, max_array_items: Option<usize>) -> Value {
+    match value {
+        Value::String(s) => match max_string_length {
+            Some(max) if s.chars().count() > max => Value::String(s.chars().take(max).collect()),
+            _ => Value::String(s),
+        },
+        Value::Array(items) => {
+            let keep = max_array_items.unwrap_or(items.len());
+            Value::Array(
+                items
+                    .into_iter()
+                    .take(keep)
+                    .map(|item| truncate(item, max_string_length, max_array_items))
+                    .collect(),
+            )
+        }
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, child)| (key, truncate(child, max_string_length, max_array_items)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// Renders a top-level array of objects as CSV. The columns are the union of
+/// all keys, in order of first appearance; a missing key or `null` becomes an
+/// empty cell, and nested objects or arrays are written as JSON.
+pub fn to_csv(body: &Value) -> Result<String, String> {
+    let Value::Array(rows) = body else {
+        return Err("to_csv requires the body to be a JSON array".to_string());
+    };
+    let mut columns: Vec<&str> = Vec::new();
+    for (index, row) in rows.iter().enumerate() {
+        let Value::Object(row) = row else {
+            return Err(format!("to_csv requires an array of objects; item {} is not an object", index));
+        };
+        for key in row.keys() {
+            if !columns.contains(&key.as_str()) {
+                columns.push(key);
+            }
+        }
+    }
+
+    let mut csv = String::new();
+    push_csv_line(&mut csv, columns.iter().map(|c| c.to_string()));
+    for row in rows {
+        push_csv_line(
+            &mut csv,
+            columns.iter().map(|column| match row.get(*column) {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(s)) => s.clone(),
+                Some(other) => other.to_string(),
+            }),
+        );
+    }
+    Ok(csv)
+}
+
+/// Appends one RFC 4180 line, quoting fields that need it.
+fn push_csv_line(csv: &mut String, fields: impl Iterator<Item = String>) {
+    for (i, field) in fields.enumerate() {
+        if i > 0 {
+            csv.push(',');
+        }
+        if field.contains([',', '"', '\n', '\r']) {
+            csv.push('"');
+            csv.push_str(&field.replace('"', "\"\""));
+            csv.push('"');
+        } else {
+            csv.push_str(&field);
+        }
+    }
+    csv.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn sort_keys_orders_deeply_nested_objects() {
+        let value: Value = serde_json::from_str(
+            r#"{"b":{"z":1,"a":{"y":[{"d":1,"c":2}],"x":null}},"a":[{"k":{"n":1,"m":2}}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::to_string(&sort_keys(value)).unwrap(),
+            r#"{"a":[{"k":{"m":2,"n":1}}],"b":{"a":{"x":null,"y":[{"c":2,"d":1}]},"z":1}}"#
+        );
+    }
+
+    #[test]
+    fn sort_keys_keeps_array_order_and_scalars() {
+        assert_eq!(sort_keys(json!([3, 1, {"b": 2, "a": 1}])), json!([3, 1, {"a": 1, "b": 2}]));
+        assert_eq!(sort_keys(json!("text")), json!("text"));
+    }
+
+    fn pipeline(value: Value) -> Vec<Transform> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn pipeline_order_changes_the_result() {
+        let body = json!({"user": {"name": "ada", "tags": ["x", "y"]}, "id": 7});
+        let select_then_flatten = pipeline(json!([
+            {"type": "select", "paths": ["user"]},
+            {"type": "flatten"},
+        ]));
+        assert_eq!(
+            apply_all(&select_then_flatten, body.clone()),
+            json!({"user.name": "ada", "user.tags.0": "x", "user.tags.1": "y"})
+        );
+        let flatten_then_select = pipeline(json!([
+            {"type": "flatten", "separator": "/"},
+            {"type": "select", "paths": ["id", "user"]},
+        ]));
+        assert_eq!(apply_all(&flatten_then_select, body), json!({"id": 7, "user": null}));
+    }
+
+    #[test]
+    fn redact_key_case_and_truncate_chain() {
+        let body = json!({
+            "userName": "ada lovelace",
+            "apiToken": "secret",
+            "recentIds": [1, 2, 3, 4],
+        });
+        let transforms = pipeline(json!([
+            {"type": "redact", "path": "apiToken"},
+            {"type": "key_case", "case": "snake"},
+            {"type": "truncate", "max_string_length": 3, "max_array_items": 2},
+        ]));
+        assert_eq!(
+            apply_all(&transforms, body),
+            json!({"user_name": "ada", "api_token": "[RE", "recent_ids": [1, 2]})
+        );
+    }
+
+    #[test]
+    fn validate_rejects_bad_paths_and_empty_separator() {
+        for bad in [
+            json!({"type": "select", "paths": ["a[x"]}),
+            json!({"type": "redact", "path": "a[x"}),
+            json!({"type": "flatten", "separator": ""}),
+        ] {
+            let transform: Transform = serde_json::from_value(bad.clone()).unwrap();
+            assert!(transform.validate().is_err(), "{} should be rejected", bad);
+        }
+    }
+}
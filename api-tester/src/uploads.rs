@@ -0,0 +1,128 @@
+//! Temporary storage for files posted to `/upload`, so a later `/proxy` call
+//! can stream them as its request body via `body_upload_id` instead of
+//! inlining them in JSON.
+//!
+//! Uploads are single use: taking one removes it from the store, and the
+//! returned guard deletes the file once the request no longer needs it.
+//! Files that are never used are removed by `sweep` after `UPLOAD_TTL`.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::web::Bytes;
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+
+pub const UPLOAD_TTL: Duration = Duration::from_secs(600);
+pub const MAX_UPLOAD_BYTES: u64 = 100 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Upload {
+    pub id: String,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub size: u64,
+    #[serde(skip)]
+    pub path: PathBuf,
+    #[serde(skip)]
+    created: Instant,
+}
+
+pub struct UploadStore {
+    dir: PathBuf,
+    files: Mutex<HashMap<String, Upload>>,
+    counter: AtomicU64,
+}
+
+impl UploadStore {
+    pub fn new() -> std::io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!("api-tester-uploads-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            files: Mutex::new(HashMap::new()),
+            counter: AtomicU64::new(0),
+        })
+    }
+
+    /// Streams an uploaded file to disk and registers it.
+    pub async fn save<S, E>(
+        &self,
+        filename: Option<String>,
+        content_type: Option<String>,
+        mut chunks: S,
+    ) -> Result<Upload, String>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: std::fmt::Display,
+    {
+        let id = format!(
+            "{:x}{:04x}",
+            chrono::Utc::now().timestamp_micros(),
+            self.counter.fetch_add(1, Ordering::Relaxed) & 0xffff
+        );
+        let path = self.dir.join(&id);
+
+        let mut file = tokio::fs::File::create(&path).await.map_err(|e| e.to_string())?;
+        let mut size = 0u64;
+        let written: Result<(), String> = async {
+            while let Some(chunk) = chunks.next().await.transpose().map_err(|e| e.to_string())? {
+                size += chunk.len() as u64;
+                if size > MAX_UPLOAD_BYTES {
+                    return Err(format!("Upload exceeds the limit of {} bytes", MAX_UPLOAD_BYTES));
+                }
+                file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+            }
+            file.flush().await.map_err(|e| e.to_string())
+        }
+        .await;
+        if let Err(e) = written {
+            let _ = tokio::fs::remove_file(&path).await;
+            return Err(e);
+        }
+
+        let upload = Upload { id: id.clone(), filename, content_type, size, path, created: Instant::now() };
+        self.files.lock().unwrap().insert(id, upload.clone());
+        Ok(upload)
+    }
+
+    /// Removes an upload from the store for use as a request body. The file
+    /// stays on disk until the returned guard is dropped.
+    pub fn take(&self, id: &str) -> Option<(Upload, UploadGuard)> {
+        let upload = self.files.lock().unwrap().remove(id)?;
+        let guard = UploadGuard(upload.path.clone());
+        Some((upload, guard))
+    }
+
+    /// Deletes uploads older than `UPLOAD_TTL` that were never used.
+    pub fn sweep(&self) {
+        let expired: Vec<Upload> = {
+            let mut files = self.files.lock().unwrap();
+            let ids: Vec<String> = files
+                .iter()
+                .filter(|(_, upload)| upload.created.elapsed() > UPLOAD_TTL)
+                .map(|(id, _)| id.clone())
+                .collect();
+            ids.iter().filter_map(|id| files.remove(id)).collect()
+        };
+        for upload in expired {
+            log::info!("Removing expired upload {}", upload.id);
+            let _ = std::fs::remove_file(&upload.path);
+        }
+    }
+}
+
+/// Deletes an upload's file when the request using it is finished.
+pub struct UploadGuard(PathBuf);
+
+impl Drop for UploadGuard {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.0) {
+            log::warn!("Failed to remove upload {}: {}", self.0.display(), e);
+        }
+    }
+}
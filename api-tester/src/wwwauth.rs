@@ -0,0 +1,181 @@
+//! Parsing of `WWW-Authenticate` challenges (RFC 9110 section 11.6.1).
+//!
+//! A header may carry several challenges, and challenges and their
+//! parameters are both comma separated, so a bare word that is not followed
+//! by `=` is what starts the next challenge. Parameter names are
+//! case-insensitive and are returned lowercased.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Challenge {
+    pub scheme: String,
+    /// The opaque credential-style form some schemes use instead of
+    /// parameters, e.g. `Negotiate <token>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token68: Option<String>,
+    pub params: BTreeMap<String, String>,
+}
+
+/// Parses every challenge in one or more header values.
+pub fn parse_all<'a>(values: impl IntoIterator<Item = &'a str>) -> Vec<Challenge> {
+    values.into_iter().flat_map(parse).collect()
+}
+
+pub fn parse(header: &str) -> Vec<Challenge> {
+    let chars: Vec<char> = header.chars().collect();
+    let mut pos = 0;
+    let mut challenges = Vec::new();
+
+    loop {
+        skip(&chars, &mut pos, |c| c.is_whitespace() || c == ',');
+        let scheme = read_word(&chars, &mut pos);
+        if scheme.is_empty() {
+            break;
+        }
+        let mut challenge = Challenge { scheme, token68: None, params: BTreeMap::new() };
+
+        loop {
+            let before = pos;
+            skip(&chars, &mut pos, |c| c.is_whitespace() || c == ',');
+            // A token68 can only follow the scheme directly, never a comma.
+            let token68_allowed = challenge.token68.is_none()
+                && challenge.params.is_empty()
+                && !chars[before..pos].contains(&',');
+            let start = pos;
+            let name = read_word(&chars, &mut pos);
+            if name.is_empty() {
+                break;
+            }
+            skip(&chars, &mut pos, char::is_whitespace);
+
+            if chars.get(pos) != Some(&'=') {
+                // A bare word right after the scheme is a token68; anywhere
+                // else it is the scheme of the next challenge.
+                if token68_allowed && followed_by_end(&chars, pos) {
+                    challenge.token68 = Some(name);
+                    continue;
+                }
+                pos = start;
+                break;
+            }
+
+            // Trailing `=` padding with nothing after it is a token68.
+            let mut lookahead = pos;
+            skip(&chars, &mut lookahead, |c| c == '=');
+            if token68_allowed && followed_by_end(&chars, lookahead) {
+                challenge.token68 = Some(chars[start..lookahead].iter().collect::<String>().trim().to_string());
+                pos = lookahead;
+                continue;
+            }
+
+            pos += 1;
+            skip(&chars, &mut pos, char::is_whitespace);
+            let value = if chars.get(pos) == Some(&'"') {
+                read_quoted(&chars, &mut pos)
+            } else {
+                read_word(&chars, &mut pos)
+            };
+            challenge.params.insert(name.to_ascii_lowercase(), value);
+        }
+
+        challenges.push(challenge);
+    }
+    challenges
+}
+
+fn skip(chars: &[char], pos: &mut usize, pred: impl Fn(char) -> bool) {
+    while chars.get(*pos).is_some_and(|&c| pred(c)) {
+        *pos += 1;
+    }
+}
+
+fn read_word(chars: &[char], pos: &mut usize) -> String {
+    let start = *pos;
+    skip(chars, pos, |c| !c.is_whitespace() && c != ',' && c != '=' && c != '"');
+    chars[start..*pos].iter().collect()
+}
+
+/// Reads a quoted string starting at the opening quote, undoing `\` escapes.
+fn read_quoted(chars: &[char], pos: &mut usize) -> String {
+    let mut value = String::new();
+    *pos += 1;
+    while let Some(&c) = chars.get(*pos) {
+        *pos += 1;
+        match c {
+            '"' => break,
+            '\\' => {
+                if let Some(&escaped) = chars.get(*pos) {
+                    value.push(escaped);
+                    *pos += 1;
+                }
+            }
+            other => value.push(other),
+        }
+    }
+    value
+}
+
+/// True when only whitespace remains before a comma or the end of input.
+fn followed_by_end(chars: &[char], mut pos: usize) -> bool {
+    skip(chars, &mut pos, char::is_whitespace);
+    matches!(chars.get(pos), None | Some(','))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn challenge(scheme: &str, token68: Option<&str>, params: &[(&str, &str)]) -> Challenge {
+        Challenge {
+            scheme: scheme.to_string(),
+            token68: token68.map(str::to_string),
+            params: params.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn several_challenges_in_one_header() {
+        assert_eq!(
+            parse(r#"Basic realm="api", Bearer realm="api", error="invalid_token", Digest Realm=x, qop=auth"#),
+            vec![
+                challenge("Basic", None, &[("realm", "api")]),
+                challenge("Bearer", None, &[("realm", "api"), ("error", "invalid_token")]),
+                challenge("Digest", None, &[("realm", "x"), ("qop", "auth")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn quoted_values_keep_commas_and_escapes() {
+        assert_eq!(
+            parse(r#"Bearer error_description="expired, \"renew\" it", scope="read write""#),
+            vec![challenge(
+                "Bearer",
+                None,
+                &[("error_description", r#"expired, "renew" it"#), ("scope", "read write")]
+            )]
+        );
+    }
+
+    #[test]
+    fn token68_follows_the_scheme_with_or_without_padding() {
+        assert_eq!(
+            parse("Negotiate YIIB9wYGKwYBBQUCoIIB6zCCAeeg==, NTLM, Basic realm=x"),
+            vec![
+                challenge("Negotiate", Some("YIIB9wYGKwYBBQUCoIIB6zCCAeeg=="), &[]),
+                challenge("NTLM", None, &[]),
+                challenge("Basic", None, &[("realm", "x")]),
+            ]
+        );
+        assert_eq!(parse("Negotiate abc123"), vec![challenge("Negotiate", Some("abc123"), &[])]);
+    }
+
+    #[test]
+    fn values_from_separate_headers_are_combined() {
+        let challenges = parse_all(["Basic realm=a", "", "Bearer"]);
+        assert_eq!(challenges, vec![challenge("Basic", None, &[("realm", "a")]), challenge("Bearer", None, &[])]);
+    }
+}